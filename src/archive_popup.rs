@@ -0,0 +1,7 @@
+use crate::list_popup::ListPopup;
+use crate::task::Task;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Popup to look through the archived tasks and bring them back
+pub type ArchivePopup = ListPopup<Rc<RefCell<Task>>>;
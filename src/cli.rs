@@ -0,0 +1,328 @@
+use crate::config::Config;
+use crate::task::{Priority, Task, TaskDB, TaskId};
+use crate::task_view::{GroupBy, LabelMatch};
+use crate::{paths, storage};
+use crate::venom::Venom;
+use crate::due_date::{days_before, parse_due_date};
+use crate::query::Query;
+use crate::recurrence::Recurrence;
+use crate::sort::Sort;
+use chrono::Local;
+use clap::{Args, Parser, Subcommand};
+use color_eyre::eyre::{eyre, Result};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Terminal todo list. Running without a subcommand opens the full screen interface.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Task database to use instead of the last one opened from the app
+    #[arg(short, long, global = true, env = "VENOM_FILE")]
+    pub file: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Commands which operate on the task database without launching the TUI
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Add a new task
+    Add(AddArgs),
+    /// List tasks along with the ids used by the other commands
+    List(ListArgs),
+    /// Mark a task as done
+    Done {
+        /// Id of the task as printed by `venom list`
+        id: TaskId,
+    },
+    /// Remove a task
+    Rm {
+        /// Id of the task as printed by `venom list`
+        id: TaskId,
+    },
+    /// Move finished tasks to the archive
+    Archive {
+        /// Id of a finished task. Without one every finished task is archived.
+        id: Option<TaskId>,
+        /// Only archive tasks finished at least this many days ago
+        #[arg(short, long, conflicts_with = "id")]
+        days: Option<u32>,
+    },
+    /// Move an archived task back into the task list
+    Restore {
+        /// Id of the task as printed by `venom list --archived`
+        id: TaskId,
+    },
+    /// Create a task database for the project in the current directory. It is used instead of the
+    /// global one whenever venom runs in this directory or below it.
+    Init,
+}
+
+#[derive(Debug, Args)]
+pub struct AddArgs {
+    /// Title of the new task
+    title: String,
+    /// Due date, such as "tomorrow 17:00", "fri 9am", "in 3 days" or "2024-03-05"
+    #[arg(short, long)]
+    due: Option<String>,
+    /// Short name of an existing label. Can be given more than once.
+    #[arg(short, long)]
+    label: Vec<String>,
+    /// One of none, low, medium or high
+    #[arg(short, long)]
+    priority: Option<Priority>,
+    /// Free form notes
+    #[arg(short, long)]
+    notes: Option<String>,
+    /// Repeat rule, such as "daily", "weekdays", "every 2 weeks", "monthly on 15", "yearly" or
+    /// "3 days after completion"
+    #[arg(short, long)]
+    repeat: Option<String>,
+    /// Id of the task to add this as a subtask of
+    #[arg(long)]
+    parent: Option<TaskId>,
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// Only show tasks with this label. Can be given more than once to show tasks with any of the
+    /// labels.
+    #[arg(short, long)]
+    label: Vec<String>,
+    /// Only show tasks with every one of the labels given with --label
+    #[arg(long)]
+    all_labels: bool,
+    /// Only show tasks matching a query, like "priority>=medium due<1w !done"
+    #[arg(short, long)]
+    query: Option<String>,
+    /// Start from a named view from the config or saved from the app
+    #[arg(long)]
+    view: Option<String>,
+    /// Order of the tasks, like "priority desc, due asc, undated first"
+    #[arg(short, long)]
+    sort: Option<Sort>,
+    /// Split the tasks into sections by label, priority, due or done
+    #[arg(short, long)]
+    group: Option<GroupBy>,
+    /// Leave out completed tasks
+    #[arg(long)]
+    hide_done: bool,
+    /// List the archived tasks instead, most recently finished first
+    #[arg(long)]
+    archived: bool,
+    /// Print the tasks as json instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+/// Run a single command against the saved task database
+pub fn run(command: Command, save_path: PathBuf, config: Config) -> Result<()> {
+    match command {
+        Command::Add(args) => add(&mut Venom::new(save_path, config)?, args),
+        Command::List(args) => list(&mut Venom::new(save_path, config)?, args),
+        Command::Done { id } => done(&mut Venom::new(save_path, config)?, id),
+        Command::Rm { id } => rm(&mut Venom::new(save_path, config)?, id),
+        Command::Archive { id, days } => archive(&mut Venom::new(save_path, config)?, id, days),
+        Command::Restore { id } => restore(&mut Venom::new(save_path, config)?, id),
+        Command::Init => init(),
+    }
+}
+
+fn init() -> Result<()> {
+    let path = paths::project_database(&std::env::current_dir()?);
+    if path.exists() {
+        return Err(eyre!("{} already exists", path.display()));
+    }
+    storage::save(&TaskDB::new(), &path)?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+fn add(app: &mut Venom, args: AddArgs) -> Result<()> {
+    let labels = app.task_db().labels_by_tags(&args.label.join(" "))?;
+    let due_date = match &args.due {
+        None => None,
+        Some(due) => parse_due_date(due, Local::now())?,
+    };
+    let recurrence = match &args.repeat {
+        None => None,
+        Some(repeat) => Recurrence::parse(repeat, due_date)?,
+    };
+
+    let task = Task::builder()
+        .with_title(&args.title)
+        .with_notes(args.notes.as_deref().unwrap_or_default())
+        .with_priority(args.priority.unwrap_or_default())
+        .with_due_date(due_date)
+        .with_labels(labels)
+        .with_recurrence(recurrence)
+        .build_rcc();
+    app.task_db_mut().add_task(Rc::clone(&task));
+    let id = task.borrow().id();
+    app.task_db().set_parent(id, args.parent)?;
+    app.try_save_file()?;
+
+    println!("{}", task.borrow().id());
+    Ok(())
+}
+
+fn list(app: &mut Venom, args: ListArgs) -> Result<()> {
+    if args.archived {
+        return list_archived(app, args.json);
+    }
+    if let Some(view) = &args.view {
+        app.switch_view_named(view)?;
+    }
+    if !args.label.is_empty() {
+        let labels = app.task_db().labels_by_tags(&args.label.join(" "))?;
+        app.task_view_mut().set_label_filter(labels);
+    }
+    if args.all_labels {
+        app.task_view_mut().set_label_match(LabelMatch::All);
+    }
+    if let Some(sort) = args.sort {
+        app.task_view_mut().set_sort(sort);
+    }
+    if let Some(group) = args.group {
+        app.task_view_mut().set_group_by(group);
+    }
+    if let Some(query) = &args.query {
+        let query = Query::parse(query)?;
+        app.task_view_mut().set_query(query);
+    }
+    app.update_view();
+
+    let tasks = app
+        .task_view()
+        .tasks()
+        .iter()
+        .enumerate()
+        .filter(|(_, task)| !args.hide_done || !task.borrow().is_done())
+        .collect::<Vec<_>>();
+
+    if args.json {
+        let tasks = tasks.iter().map(|(_, task)| task).collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&tasks)?);
+        return Ok(());
+    }
+
+    let label_width = tasks
+        .iter()
+        .map(|(_, task)| task.borrow().labels_string().chars().count())
+        .max()
+        .unwrap_or_default();
+    let groups = app.task_view().groups();
+    let group_of = |idx: usize| {
+        groups
+            .iter()
+            .position(|group| (group.start..group.start + group.len).contains(&idx))
+    };
+    // count the rows printed rather than the whole group, which may include hidden done tasks.
+    // Groups with nothing left to print get no header at all.
+    let mut shown_in_group = vec![0; groups.len()];
+    for group in tasks.iter().filter_map(|(idx, _)| group_of(*idx)) {
+        shown_in_group[group] += 1;
+    }
+    let mut last_group = None;
+    for (idx, task) in tasks.iter().copied() {
+        let group = group_of(idx);
+        if let (Some(group), true) = (group, group != last_group) {
+            println!("{} ({})", groups[group].title, shown_in_group[group]);
+            last_group = Some(group);
+        }
+        let indent = "  ".repeat(app.task_view().depth(idx));
+        let task = task.borrow();
+        println!(
+            "{:>3} {} {:<label_width$} {:<6} {:>11} {:>5}  {}{}",
+            task.id(),
+            if task.is_done() { "[x]" } else { "[ ]" },
+            task.labels_string(),
+            task.priority().to_string(),
+            task.date_string(),
+            task.time_string(),
+            indent,
+            task.title()
+        );
+    }
+    Ok(())
+}
+
+fn list_archived(app: &Venom, json: bool) -> Result<()> {
+    let mut tasks = app.task_db().archive().to_vec();
+    tasks.sort_by_key(|task| std::cmp::Reverse(task.borrow().completed()));
+    if json {
+        println!("{}", serde_json::to_string_pretty(&tasks)?);
+        return Ok(());
+    }
+
+    let label_width = tasks
+        .iter()
+        .map(|task| task.borrow().labels_string().chars().count())
+        .max()
+        .unwrap_or_default();
+    for task in tasks {
+        let task = task.borrow();
+        let completed = task
+            .completed()
+            .map(|completed| completed.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        println!(
+            "{:>3} {:<label_width$} {:>10}  {}",
+            task.id(),
+            task.labels_string(),
+            completed,
+            task.title()
+        );
+    }
+    Ok(())
+}
+
+fn done(app: &mut Venom, id: TaskId) -> Result<()> {
+    let found = app.task_db().update_task(id, |task| {
+        if !task.is_done() {
+            task.toggle_done();
+        }
+    });
+    if !found {
+        return Err(eyre!("no task with id {id}"));
+    }
+    let is_done = app
+        .task_db()
+        .task_by_id(id)
+        .is_some_and(|task| task.borrow().is_done());
+    if app.config().defaults.complete_subtasks && is_done {
+        app.task_db().complete_descendants(id);
+    }
+    app.try_save_file()?;
+    Ok(())
+}
+
+fn archive(app: &mut Venom, id: Option<TaskId>, days: Option<u32>) -> Result<()> {
+    match id {
+        Some(id) => app.task_db_mut().archive_task(id)?,
+        None => {
+            let before = days.map(|days| days_before(Local::now(), days)).transpose()?;
+            let count = app.task_db_mut().archive_done(before);
+            println!("archived {count} tasks");
+        }
+    }
+    app.try_save_file()?;
+    Ok(())
+}
+
+fn restore(app: &mut Venom, id: TaskId) -> Result<()> {
+    app.task_db_mut()
+        .restore_task(id)
+        .ok_or_else(|| eyre!("no archived task with id {id}"))?;
+    app.try_save_file()?;
+    Ok(())
+}
+
+fn rm(app: &mut Venom, id: TaskId) -> Result<()> {
+    app.task_db_mut()
+        .remove_task_by_id(id)
+        .ok_or_else(|| eyre!("no task with id {id}"))?;
+    app.try_save_file()?;
+    Ok(())
+}
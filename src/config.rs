@@ -0,0 +1,502 @@
+use crate::due_date::Urgency;
+use crate::task::Priority;
+use crate::task_view::{CompletedTaskView, ViewSpec};
+use color_eyre::eyre::{Result, WrapErr};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::style::{Color, Style, Stylize};
+use serde::{de, Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use strum::IntoEnumIterator;
+
+/// User settings, read from `config.toml`. Everything is optional and falls back to the defaults.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Keys for the actions in the main view
+    pub keys: KeyMap,
+    /// Colors used throughout the interface
+    pub theme: Theme,
+    /// Starting state of the app
+    pub defaults: Defaults,
+    /// How due dates are shown
+    pub due: DueSettings,
+    /// Named views, listed before the ones saved from the app
+    pub views: Vec<ViewSpec>,
+}
+
+impl Config {
+    /// Read the config file. A missing file gives the default config.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err).wrap_err(format!("could not open {}", path.display())),
+        };
+        toml::from_str(&text).wrap_err(format!("could not read {}", path.display()))
+    }
+}
+
+/// Settings for how the app starts out
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Defaults {
+    /// How completed tasks are shown at startup
+    pub completed_view: CompletedTaskView,
+    /// Whether marking a task done also marks all of its subtasks done
+    pub complete_subtasks: bool,
+    /// Name of the view to start in
+    pub view: Option<String>,
+    /// Move finished tasks to the archive once they have been done for this many days
+    pub archive_after_days: Option<u32>,
+}
+
+/// Settings for how due dates are shown in the main view
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DueSettings {
+    /// Tasks due within this many days, after tomorrow, are highlighted as due soon
+    pub soon_days: u32,
+    /// Show how long until each task is due, like `in 2h` or `3d overdue`
+    pub relative_column: bool,
+}
+
+impl Default for DueSettings {
+    fn default() -> Self {
+        Self {
+            soon_days: 7,
+            relative_column: true,
+        }
+    }
+}
+
+/// Things that can be done from the main view. Each can be bound to any number of keys.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, strum::EnumIter, strum::Display,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    #[strum(to_string = "Quit")]
+    Quit,
+    #[strum(to_string = "Down")]
+    Down,
+    #[strum(to_string = "Up")]
+    Up,
+    #[strum(to_string = "Page Down")]
+    PageDown,
+    #[strum(to_string = "Page Up")]
+    PageUp,
+    #[strum(to_string = "First")]
+    First,
+    #[strum(to_string = "Last")]
+    Last,
+    #[strum(to_string = "Add")]
+    Add,
+    #[strum(to_string = "Add Subtask")]
+    AddSubtask,
+    #[strum(to_string = "Edit Task")]
+    Edit,
+    #[strum(to_string = "Edit Labels")]
+    EditLabels,
+    #[strum(to_string = "Open")]
+    OpenDatabase,
+    #[strum(to_string = "Delete")]
+    Delete,
+    #[strum(to_string = "Repeat")]
+    Repeat,
+    #[strum(to_string = "Undo")]
+    Undo,
+    #[strum(to_string = "Redo")]
+    Redo,
+    #[strum(to_string = "Done")]
+    ToggleDone,
+    #[strum(to_string = "Completed")]
+    CycleCompletedView,
+    #[strum(to_string = "Label")]
+    CycleLabelFilter,
+    #[strum(to_string = "Filter Labels")]
+    FilterLabels,
+    #[strum(to_string = "Any/All Labels")]
+    ToggleLabelMatch,
+    #[strum(to_string = "Query")]
+    Query,
+    #[strum(to_string = "Find")]
+    Find,
+    #[strum(to_string = "Views")]
+    PickView,
+    #[strum(to_string = "Sort")]
+    Sort,
+    #[strum(to_string = "Move Down")]
+    MoveDown,
+    #[strum(to_string = "Move Up")]
+    MoveUp,
+    #[strum(to_string = "Indent")]
+    Indent,
+    #[strum(to_string = "Outdent")]
+    Outdent,
+    #[strum(to_string = "Fold")]
+    ToggleCollapsed,
+    #[strum(to_string = "Group")]
+    CycleGroup,
+    #[strum(to_string = "Mark")]
+    ToggleMark,
+    #[strum(to_string = "Visual")]
+    VisualMode,
+    #[strum(to_string = "Set Priority")]
+    SetPriority,
+    #[strum(to_string = "Set Labels")]
+    SetLabels,
+    #[strum(to_string = "Set Due Date")]
+    SetDueDate,
+    #[strum(to_string = "Archive")]
+    Archive,
+    #[strum(to_string = "Show Archive")]
+    ShowArchive,
+}
+
+impl Action {
+    /// Actions listed at the bottom of the main view
+    pub const HINTS: [Action; 7] = [
+        Action::Quit,
+        Action::Add,
+        Action::Delete,
+        Action::Undo,
+        Action::Edit,
+        Action::EditLabels,
+        Action::OpenDatabase,
+    ];
+
+    /// Keys used when the config does not say otherwise
+    pub fn default_keys(&self) -> &'static [&'static str] {
+        match self {
+            Self::Quit => &["esc", "ctrl-c"],
+            Self::Down => &["down", "j"],
+            Self::Up => &["up", "k"],
+            Self::PageDown => &["pagedown"],
+            Self::PageUp => &["pageup"],
+            Self::First => &["home", "g"],
+            Self::Last => &["end", "G"],
+            Self::Add => &["a"],
+            Self::AddSubtask => &["A"],
+            Self::Edit => &["enter"],
+            Self::EditLabels => &["l"],
+            Self::OpenDatabase => &["o"],
+            Self::Delete => &["d"],
+            Self::Repeat => &["r"],
+            Self::Undo => &["u"],
+            Self::Redo => &["ctrl-r"],
+            Self::ToggleDone => &["space"],
+            Self::CycleCompletedView => &["tab"],
+            Self::CycleLabelFilter => &["f"],
+            Self::FilterLabels => &["#"],
+            Self::ToggleLabelMatch => &["F"],
+            Self::Query => &["/"],
+            Self::Find => &["ctrl-f"],
+            Self::PickView => &["V"],
+            Self::Sort => &["s"],
+            Self::MoveDown => &["J"],
+            Self::MoveUp => &["K"],
+            Self::Indent => &[">"],
+            Self::Outdent => &["<"],
+            Self::ToggleCollapsed => &["z"],
+            Self::CycleGroup => &["="],
+            Self::ToggleMark => &["x"],
+            Self::VisualMode => &["v"],
+            Self::SetPriority => &["p"],
+            Self::SetLabels => &["L"],
+            Self::SetDueDate => &["D"],
+            Self::Archive => &["X"],
+            Self::ShowArchive => &["ctrl-x"],
+        }
+    }
+}
+
+/// A key along with the modifiers held down with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // shift is already part of the character for character keys, and terminals do not agree
+        // on whether to report it
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+}
+
+impl From<KeyEvent> for KeyBinding {
+    fn from(ke: KeyEvent) -> Self {
+        Self::new(ke.code, ke.modifiers)
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    /// Parses keys like `a`, `G`, `ctrl-r`, `alt-enter` or `pagedown`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        while let Some((prefix, key)) = rest.split_once('-') {
+            if key.is_empty() {
+                break;
+            }
+            modifiers |= match prefix.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier \"{prefix}\" in \"{text}\"")),
+            };
+            rest = key;
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => {
+                if modifiers.contains(KeyModifiers::SHIFT) {
+                    KeyCode::Char(c.to_ascii_uppercase())
+                } else {
+                    KeyCode::Char(c)
+                }
+            }
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "esc" | "escape" => KeyCode::Esc,
+                "enter" | "return" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "space" => KeyCode::Char(' '),
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => match name.strip_prefix('f').map(str::parse::<u8>) {
+                    Some(Ok(n)) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key \"{text}\"")),
+                },
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "f{n}"),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            code => write!(f, "{}", format!("{code:?}").to_ascii_lowercase()),
+        }
+    }
+}
+
+/// Which action each key triggers in the main view
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: HashMap<KeyBinding, Action>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let mut map = Self {
+            bindings: HashMap::new(),
+        };
+        for action in Action::iter() {
+            let keys = action
+                .default_keys()
+                .iter()
+                .map(|key| key.parse().expect("default keys are valid"))
+                .collect();
+            map.bind(action, keys);
+        }
+        map
+    }
+}
+
+impl KeyMap {
+    /// The action triggered by a key press, if any
+    pub fn action(&self, ke: KeyEvent) -> Option<Action> {
+        self.bindings.get(&KeyBinding::from(ke)).copied()
+    }
+
+    /// Every key bound to an action, in a stable order
+    pub fn keys(&self, action: Action) -> Vec<KeyBinding> {
+        let mut keys = self
+            .bindings
+            .iter()
+            .filter(|(_, &other)| other == action)
+            .map(|(&key, _)| key)
+            .collect::<Vec<_>>();
+        keys.sort_by_key(|key| key.to_string());
+        keys
+    }
+
+    /// Short description of the keys for an action, for hints in the interface
+    pub fn hint(&self, action: Action) -> String {
+        match self.keys(action).first() {
+            Some(key) => format!("{key}: {action}"),
+            None => String::new(),
+        }
+    }
+
+    /// Replace the keys of an action. The keys are taken away from whatever action had them.
+    pub fn bind(&mut self, action: Action, keys: Vec<KeyBinding>) {
+        self.bindings.retain(|_, &mut other| other != action);
+        for key in keys {
+            self.bindings.insert(key, action);
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// Either `undo = "u"` or `undo = ["u", "ctrl-z"]`
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Keys {
+            One(String),
+            Many(Vec<String>),
+        }
+
+        let mut map = Self::default();
+        let overrides = HashMap::<Action, Keys>::deserialize(deserializer)?;
+        for (action, keys) in overrides {
+            let keys = match keys {
+                Keys::One(key) => vec![key],
+                Keys::Many(keys) => keys,
+            };
+            let keys = keys
+                .iter()
+                .map(|key| key.parse())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(de::Error::custom)?;
+            map.bind(action, keys);
+        }
+        Ok(map)
+    }
+}
+
+/// Colors of the interface. Any color ratatui understands can be used, such as `red`,
+/// `lightblue`, `#ff8800` or an ansi index like `208`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    #[serde(deserialize_with = "deserialize_color")]
+    pub priority_none: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub priority_low: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub priority_medium: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub priority_high: Color,
+    /// Background of the selected row in the main view. Left unset, only the `*` marker shows
+    /// the selection.
+    #[serde(deserialize_with = "deserialize_optional_color")]
+    pub selection: Option<Color>,
+    /// Borders of the main view and summary
+    #[serde(deserialize_with = "deserialize_color")]
+    pub border: Color,
+    /// The current item in popups
+    #[serde(deserialize_with = "deserialize_color")]
+    pub highlight: Color,
+    /// The part of a popup which is not focused
+    #[serde(deserialize_with = "deserialize_color")]
+    pub inactive: Color,
+    /// Cursor of the text editor in insert mode
+    #[serde(deserialize_with = "deserialize_color")]
+    pub cursor: Color,
+    /// Error messages
+    #[serde(deserialize_with = "deserialize_color")]
+    pub error: Color,
+    /// Due dates which have passed
+    #[serde(deserialize_with = "deserialize_color")]
+    pub overdue: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub due_today: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub due_tomorrow: Color,
+    /// Due dates within `soon_days` of the `[due]` settings
+    #[serde(deserialize_with = "deserialize_color")]
+    pub due_soon: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            priority_none: Priority::None.formatting().0,
+            priority_low: Priority::Low.formatting().0,
+            priority_medium: Priority::Medium.formatting().0,
+            priority_high: Priority::High.formatting().0,
+            selection: None,
+            border: Color::default(),
+            highlight: Color::Blue,
+            inactive: Color::DarkGray,
+            cursor: Color::LightCyan,
+            error: Color::Red,
+            overdue: Color::Red,
+            due_today: Color::Yellow,
+            due_tomorrow: Color::LightYellow,
+            due_soon: Color::Cyan,
+        }
+    }
+}
+
+impl Theme {
+    pub fn priority(&self, priority: Priority) -> Color {
+        match priority {
+            Priority::None => self.priority_none,
+            Priority::Low => self.priority_low,
+            Priority::Medium => self.priority_medium,
+            Priority::High => self.priority_high,
+        }
+    }
+
+    /// Style of the due date of a task, which stands out more the closer it is to being due
+    pub fn urgency(&self, urgency: Option<Urgency>) -> Style {
+        match urgency {
+            Some(Urgency::Overdue) => Style::default().fg(self.overdue).bold(),
+            Some(Urgency::Today) => Style::default().fg(self.due_today).bold(),
+            Some(Urgency::Tomorrow) => Style::default().fg(self.due_tomorrow),
+            Some(Urgency::Soon) => Style::default().fg(self.due_soon),
+            Some(Urgency::Later) | None => Style::default(),
+        }
+    }
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    Color::from_str(&text).map_err(|_| de::Error::custom(format!("unknown color \"{text}\"")))
+}
+
+fn deserialize_optional_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Color>, D::Error> {
+    deserialize_color(deserializer).map(Some)
+}
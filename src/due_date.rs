@@ -0,0 +1,594 @@
+use chrono::{
+    DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, Weekday,
+};
+use color_eyre::eyre::{eyre, Result};
+
+/// Time of day used when only a date is given
+const DEFAULT_HOUR: u32 = 23;
+const DEFAULT_MINUTE: u32 = 59;
+
+/// Seconds in each unit of time, for [`checked_duration`]
+pub const MINUTE: i64 = 60;
+pub const HOUR: i64 = 60 * MINUTE;
+pub const DAY: i64 = 24 * HOUR;
+pub const WEEK: i64 = 7 * DAY;
+
+/// How close a task is to being due, for highlighting it in the main view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Overdue,
+    Today,
+    Tomorrow,
+    /// Due within the configured number of days, after tomorrow
+    Soon,
+    Later,
+}
+
+impl Urgency {
+    /// How urgent a task due at `due` is at `now`
+    pub fn of(due: DateTime<Local>, now: DateTime<Local>, soon_days: u32) -> Self {
+        let today = now.date_naive();
+        let day = due.date_naive();
+        if due < now {
+            Self::Overdue
+        } else if day == today {
+            Self::Today
+        } else if day == today + Duration::days(1) {
+            Self::Tomorrow
+        } else if soon_until(today, soon_days).is_none_or(|until| day <= until) {
+            Self::Soon
+        } else {
+            Self::Later
+        }
+    }
+}
+
+/// Last day which counts as due soon, or `None` if every date does
+fn soon_until(today: NaiveDate, soon_days: u32) -> Option<NaiveDate> {
+    checked_duration(soon_days, DAY).and_then(|duration| today.checked_add_signed(duration))
+}
+
+/// How long until `due` in the largest whole unit, like `in 2h`, or `3d overdue` once it has
+/// passed
+pub fn relative_string(due: DateTime<Local>, now: DateTime<Local>) -> String {
+    let seconds = (due - now).num_seconds();
+    let amount = match seconds.abs() {
+        s if s < MINUTE => return "now".to_string(),
+        s if s < HOUR => format!("{}m", s / MINUTE),
+        s if s < 2 * DAY => format!("{}h", s / HOUR),
+        s if s < 14 * DAY => format!("{}d", s / DAY),
+        s => format!("{}w", s / WEEK),
+    };
+    match seconds < 0 {
+        true => format!("{amount} overdue"),
+        false => format!("in {amount}"),
+    }
+}
+
+/// An amount of time to move due dates by, like `+2d`, `-1w` or `3h`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shift {
+    backward: bool,
+    count: u32,
+    unit: ShiftUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShiftUnit {
+    Minutes,
+    Hours,
+    Days,
+    Weeks,
+    Months,
+    Years,
+}
+
+impl Shift {
+    /// Parse an amount of time with an optional sign. `None` if the text is not an amount of time
+    /// at all, like `2024-03-05` or `9am`, so that it can be read as a date instead.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().to_lowercase();
+        let (backward, amount) = match text.strip_prefix('-') {
+            Some(amount) => (true, amount),
+            None => (false, text.strip_prefix('+').unwrap_or(&text)),
+        };
+        let amount = amount.replace(' ', "");
+        let (count, unit) = split_count_unit(&amount)?;
+        let unit = match unit {
+            "m" | "min" | "mins" | "minute" | "minutes" => ShiftUnit::Minutes,
+            "h" | "hr" | "hrs" | "hour" | "hours" => ShiftUnit::Hours,
+            "d" | "day" | "days" => ShiftUnit::Days,
+            "w" | "wk" | "wks" | "week" | "weeks" => ShiftUnit::Weeks,
+            "mo" | "month" | "months" => ShiftUnit::Months,
+            "y" | "yr" | "yrs" | "year" | "years" => ShiftUnit::Years,
+            _ => return None,
+        };
+        Some(Self {
+            backward,
+            count,
+            unit,
+        })
+    }
+
+    /// Move a date by the amount. Whole days keep the time of day.
+    pub fn apply(&self, date: DateTime<Local>) -> Result<DateTime<Local>> {
+        let seconds = match self.unit {
+            ShiftUnit::Minutes => Some(MINUTE),
+            ShiftUnit::Hours => Some(HOUR),
+            ShiftUnit::Days => Some(DAY),
+            ShiftUnit::Weeks => Some(WEEK),
+            ShiftUnit::Months | ShiftUnit::Years => None,
+        };
+        let date_time = date.naive_local();
+        let shifted = match (seconds, self.backward) {
+            (Some(seconds), backward) => {
+                checked_duration(self.count, seconds).and_then(|duration| match backward {
+                    false => date_time.checked_add_signed(duration),
+                    true => date_time.checked_sub_signed(duration),
+                })
+            }
+            (None, backward) => {
+                let months = match self.unit {
+                    ShiftUnit::Years => self.count.checked_mul(12),
+                    _ => Some(self.count),
+                };
+                months.map(Months::new).and_then(|months| match backward {
+                    false => date_time.checked_add_months(months),
+                    true => date_time.checked_sub_months(months),
+                })
+            }
+        }
+        .ok_or_else(|| eyre!("date out of range"))?;
+        shifted
+            .and_local_timezone(Local)
+            .earliest()
+            .ok_or_else(|| eyre!("{shifted} does not exist in the local timezone"))
+    }
+}
+
+/// Parse a due date typed by the user, relative to `now`. Empty text means no due date.
+///
+/// Understands things like:
+/// - `today`, `tomorrow`, `yesterday`, `eod`, `eow`, `eom`
+/// - `fri`, `next friday`, `next week`, `next month`, `next year`
+/// - `in 3 days`, `in 2h`, `+1w`
+/// - `2024-03-05`, `5 Mar 2024`, `mar 5`
+/// - any of the above followed or preceded by a time like `9am`, `9:30 pm`, `17:00` or `noon`
+pub fn parse_due_date(text: &str, now: DateTime<Local>) -> Result<Option<DateTime<Local>>> {
+    let text = text.trim().to_lowercase();
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    // split ISO 8601 date times like 2024-03-05t14:00 into a date and a time
+    let text = text.replacen('t', " ", usize::from(is_iso_date_time(&text)));
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    let today = now.date_naive();
+
+    let mut date: Option<NaiveDate> = None;
+    let mut time: Option<NaiveTime> = None;
+    let mut exact: Option<NaiveDateTime> = None;
+
+    let mut idx = 0;
+    while idx < words.len() {
+        let word = words[idx];
+        let next = words.get(idx + 1).copied();
+        idx += 1;
+
+        match word {
+            "at" | "on" | "by" | "due" => {}
+            "today" | "tod" => date = Some(today),
+            "tomorrow" | "tom" | "tmr" | "tmrw" => date = Some(today + Duration::days(1)),
+            "yesterday" => date = Some(today - Duration::days(1)),
+            "eod" => {
+                date = Some(today);
+                time = Some(default_time());
+            }
+            "eow" => {
+                date = Some(upcoming(today, Weekday::Sun, true));
+                time = Some(default_time());
+            }
+            "eom" => {
+                let next_month = add_months(first_of_month(today), 1)?;
+                date = Some(next_month - Duration::days(1));
+                time = Some(default_time());
+            }
+            "noon" => time = NaiveTime::from_hms_opt(12, 0, 0),
+            "midnight" => time = NaiveTime::from_hms_opt(0, 0, 0),
+            "next" => {
+                let Some(next) = next else {
+                    return Err(eyre!("\"next\" needs to be followed by something"));
+                };
+                idx += 1;
+                if let Some(weekday) = parse_weekday(next) {
+                    date = Some(upcoming(today, weekday, false) + Duration::days(7));
+                } else {
+                    date = Some(match next {
+                        "day" => today + Duration::days(1),
+                        "week" | "wk" => today + Duration::weeks(1),
+                        "month" | "mo" => add_months(today, 1)?,
+                        "year" | "yr" => add_months(today, 12)?,
+                        _ => return Err(eyre!("could not understand \"next {next}\"")),
+                    });
+                }
+            }
+            "in" => {
+                let Some(amount) = next else {
+                    return Err(eyre!("\"in\" needs to be followed by an amount of time"));
+                };
+                idx += 1;
+                let (count, unit) = match split_count_unit(amount) {
+                    Some((count, "")) => {
+                        let unit = words
+                            .get(idx)
+                            .copied()
+                            .ok_or_else(|| eyre!("\"in {count}\" needs a unit like days"))?;
+                        idx += 1;
+                        (count, unit)
+                    }
+                    Some(count_unit) => count_unit,
+                    None => return Err(eyre!("could not understand \"in {amount}\"")),
+                };
+                apply_offset(now, count, unit, &mut date, &mut exact)?;
+            }
+            _ => {
+                if let Some(weekday) = parse_weekday(word) {
+                    date = Some(upcoming(today, weekday, false));
+                } else if let Some((count, unit)) = word
+                    .strip_prefix('+')
+                    .and_then(split_count_unit)
+                    .filter(|(_, unit)| !unit.is_empty())
+                {
+                    apply_offset(now, count, unit, &mut date, &mut exact)?;
+                } else if let Ok(iso) = NaiveDate::parse_from_str(word, "%Y-%m-%d") {
+                    date = Some(iso);
+                } else if let Some(month) = parse_month(word) {
+                    // mar 5 [2024]
+                    let day = next
+                        .and_then(|day| day.trim_end_matches(',').parse::<u32>().ok())
+                        .ok_or_else(|| eyre!("\"{word}\" needs to be followed by a day"))?;
+                    idx += 1;
+                    let year = take_year(&words, &mut idx);
+                    date = Some(month_day(today, year, month, day)?);
+                } else if let Some(month) = next.and_then(parse_month).filter(|_| is_number(word)) {
+                    // 5 mar [2024]
+                    idx += 1;
+                    let day = word.parse::<u32>()?;
+                    let year = take_year(&words, &mut idx);
+                    date = Some(month_day(today, year, month, day)?);
+                } else if let Some(parsed) = parse_time(word, next) {
+                    let (parsed, used_next) = parsed?;
+                    if used_next {
+                        idx += 1;
+                    }
+                    time = Some(parsed);
+                } else {
+                    return Err(eyre!("could not understand \"{word}\""));
+                }
+            }
+        }
+    }
+
+    let date_time = match (exact, date, time) {
+        (Some(exact), _, None) => exact,
+        (Some(exact), _, Some(time)) => NaiveDateTime::new(exact.date(), time),
+        (None, Some(date), time) => NaiveDateTime::new(date, time.unwrap_or_else(default_time)),
+        // a time on its own is the next time the clock shows it
+        (None, None, Some(time)) => {
+            if time > now.time() {
+                NaiveDateTime::new(today, time)
+            } else {
+                NaiveDateTime::new(today + Duration::days(1), time)
+            }
+        }
+        (None, None, None) => return Err(eyre!("no date given")),
+    };
+
+    date_time
+        .and_local_timezone(Local)
+        .earliest()
+        .map(Some)
+        .ok_or_else(|| eyre!("{date_time} does not exist in the local timezone"))
+}
+
+fn default_time() -> NaiveTime {
+    NaiveTime::from_hms_opt(DEFAULT_HOUR, DEFAULT_MINUTE, 0).unwrap()
+}
+
+fn is_iso_date_time(text: &str) -> bool {
+    text.split_once('t').is_some_and(|(date, time)| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok() && !time.is_empty()
+    })
+}
+
+fn is_number(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Splits `3d` into `(3, "d")` and `3` into `(3, "")`
+fn split_count_unit(word: &str) -> Option<(u32, &str)> {
+    let split = word.find(|c: char| !c.is_ascii_digit()).unwrap_or(word.len());
+    let count = word[..split].parse().ok()?;
+    Some((count, &word[split..]))
+}
+
+/// Move `now` forward by `count` units. Offsets of less than a day give an exact time.
+fn apply_offset(
+    now: DateTime<Local>,
+    count: u32,
+    unit: &str,
+    date: &mut Option<NaiveDate>,
+    exact: &mut Option<NaiveDateTime>,
+) -> Result<()> {
+    let today = now.date_naive();
+    let after = |seconds| {
+        checked_duration(count, seconds)
+            .and_then(|duration| today.checked_add_signed(duration))
+            .ok_or_else(|| eyre!("date out of range"))
+    };
+    let exact_after = |seconds| {
+        checked_duration(count, seconds)
+            .and_then(|duration| now.naive_local().checked_add_signed(duration))
+            .ok_or_else(|| eyre!("date out of range"))
+    };
+    match unit {
+        "m" | "min" | "mins" | "minute" | "minutes" => *exact = Some(exact_after(MINUTE)?),
+        "h" | "hr" | "hrs" | "hour" | "hours" => *exact = Some(exact_after(HOUR)?),
+        "d" | "day" | "days" => *date = Some(after(DAY)?),
+        "w" | "wk" | "wks" | "week" | "weeks" => *date = Some(after(WEEK)?),
+        "mo" | "month" | "months" => *date = Some(add_months(today, count)?),
+        "y" | "yr" | "yrs" | "year" | "years" => {
+            let months = count.checked_mul(12).ok_or_else(|| eyre!("date out of range"))?;
+            *date = Some(add_months(today, months)?)
+        }
+        _ => return Err(eyre!("unknown unit of time \"{unit}\"")),
+    }
+    Ok(())
+}
+
+/// The time `days` days before `now`
+pub fn days_before(now: DateTime<Local>, days: u32) -> Result<DateTime<Local>> {
+    checked_duration(days, DAY)
+        .and_then(|duration| now.checked_sub_signed(duration))
+        .ok_or_else(|| eyre!("{days} days ago is out of range"))
+}
+
+/// `count` lots of `seconds` as a [`Duration`], or `None` if that is too long to represent.
+/// `Duration::days` and the like panic instead.
+pub fn checked_duration(count: u32, seconds: i64) -> Option<Duration> {
+    let total = i64::from(count) * seconds;
+    (total <= Duration::max_value().num_seconds()).then(|| Duration::seconds(total))
+}
+
+fn add_months(date: NaiveDate, months: u32) -> Result<NaiveDate> {
+    date.checked_add_months(Months::new(months))
+        .ok_or_else(|| eyre!("date out of range"))
+}
+
+fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap()
+}
+
+/// The next `weekday` after `today`, or today itself if `include_today` is set and it matches
+fn upcoming(today: NaiveDate, weekday: Weekday, include_today: bool) -> NaiveDate {
+    let ahead = (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+    let ahead = if ahead == 0 && !include_today { 7 } else { ahead };
+    today + Duration::days(i64::from(ahead))
+}
+
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    Some(match word {
+        "mon" | "monday" => Weekday::Mon,
+        "tue" | "tues" | "tuesday" => Weekday::Tue,
+        "wed" | "weds" | "wednesday" => Weekday::Wed,
+        "thu" | "thur" | "thurs" | "thursday" => Weekday::Thu,
+        "fri" | "friday" => Weekday::Fri,
+        "sat" | "saturday" => Weekday::Sat,
+        "sun" | "sunday" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn parse_month(word: &str) -> Option<u32> {
+    Some(match word {
+        "jan" | "january" => 1,
+        "feb" | "february" => 2,
+        "mar" | "march" => 3,
+        "apr" | "april" => 4,
+        "may" => 5,
+        "jun" | "june" => 6,
+        "jul" | "july" => 7,
+        "aug" | "august" => 8,
+        "sep" | "sept" | "september" => 9,
+        "oct" | "october" => 10,
+        "nov" | "november" => 11,
+        "dec" | "december" => 12,
+        _ => return None,
+    })
+}
+
+/// Consume a four digit year if that is the next word
+fn take_year(words: &[&str], idx: &mut usize) -> Option<i32> {
+    let year = words
+        .get(*idx)
+        .filter(|word| word.len() == 4 && is_number(word))?
+        .parse()
+        .ok()?;
+    *idx += 1;
+    Some(year)
+}
+
+/// A day of a month. Without a year, the next time that day comes around.
+fn month_day(today: NaiveDate, year: Option<i32>, month: u32, day: u32) -> Result<NaiveDate> {
+    let invalid = || eyre!("there is no day {day} in month {month}");
+    match year {
+        Some(year) => NaiveDate::from_ymd_opt(year, month, day).ok_or_else(invalid),
+        None => {
+            let this_year = NaiveDate::from_ymd_opt(today.year(), month, day).ok_or_else(invalid)?;
+            if this_year >= today {
+                Ok(this_year)
+            } else {
+                NaiveDate::from_ymd_opt(today.year() + 1, month, day).ok_or_else(invalid)
+            }
+        }
+    }
+}
+
+/// Parses `17:00`, `9am`, `9:30pm` or `9` followed by a separate `am`/`pm` in `next`. Returns
+/// `None` if the word is not a time at all, and whether `next` was used.
+fn parse_time(word: &str, next: Option<&str>) -> Option<Result<(NaiveTime, bool)>> {
+    let (clock, suffix, used_next) = if let Some(clock) = word.strip_suffix("am") {
+        (clock, Some(false), false)
+    } else if let Some(clock) = word.strip_suffix("pm") {
+        (clock, Some(true), false)
+    } else {
+        match next {
+            Some("am") => (word, Some(false), true),
+            Some("pm") => (word, Some(true), true),
+            _ => (word, None, false),
+        }
+    };
+
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) => (hour, minute),
+        None if suffix.is_some() => (clock, "0"),
+        // a bare number could be anything, so it is not a time
+        None => return None,
+    };
+    if !is_number(hour) || !is_number(minute) {
+        return None;
+    }
+    let (mut hour, minute) = (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?);
+
+    if let Some(pm) = suffix {
+        if !(1..=12).contains(&hour) {
+            return Some(Err(eyre!("{word} is not a valid time")));
+        }
+        hour = match (hour, pm) {
+            (12, false) => 0,
+            (12, true) => 12,
+            (hour, true) => hour + 12,
+            (hour, false) => hour,
+        };
+    }
+    Some(
+        NaiveTime::from_hms_opt(hour, minute, 0)
+            .map(|time| (time, used_next))
+            .ok_or_else(|| eyre!("{word} is not a valid time")),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// A local time, for tests which need a fixed `now`
+    pub(crate) fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn due_dates() {
+        // a wednesday
+        let now = local(2024, 3, 6, 12, 0);
+        for (text, due) in [
+            ("tomorrow", local(2024, 3, 7, 23, 59)),
+            ("fri 9am", local(2024, 3, 8, 9, 0)),
+            ("in 3 days", local(2024, 3, 9, 23, 59)),
+            ("in 2h", local(2024, 3, 6, 14, 0)),
+            ("+1w", local(2024, 3, 13, 23, 59)),
+            ("next month", local(2024, 4, 6, 23, 59)),
+            ("next friday", local(2024, 3, 15, 23, 59)),
+            ("2024-03-05", local(2024, 3, 5, 23, 59)),
+            ("2024-03-05T14:30", local(2024, 3, 5, 14, 30)),
+            ("5 Mar 2024", local(2024, 3, 5, 23, 59)),
+            ("mar 5 2025 9:30 pm", local(2025, 3, 5, 21, 30)),
+            ("eod", local(2024, 3, 6, 23, 59)),
+            ("eom", local(2024, 3, 31, 23, 59)),
+            ("17:00", local(2024, 3, 6, 17, 0)),
+            // already past today, so tomorrow
+            ("9am", local(2024, 3, 7, 9, 0)),
+            ("tomorrow noon", local(2024, 3, 7, 12, 0)),
+        ] {
+            assert_eq!(parse_due_date(text, now).unwrap(), Some(due), "{text}");
+        }
+    }
+
+    #[test]
+    fn bad_due_dates() {
+        let now = local(2024, 3, 6, 12, 0);
+        assert_eq!(parse_due_date("  ", now).unwrap(), None);
+        for text in [
+            "someday",
+            "next",
+            "in 3",
+            "13pm",
+            "feb 30 2024",
+            "in 99999999 days",
+            "in 4000000000 weeks",
+            "+99999999d",
+            "in 4000000000 hours",
+            "in 400000000 years",
+            "in 4000000000 months",
+        ] {
+            assert!(parse_due_date(text, now).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn shifts() {
+        let due = local(2024, 3, 5, 9, 0);
+        for (text, shifted) in [
+            ("+2d", local(2024, 3, 7, 9, 0)),
+            ("-1w", local(2024, 2, 27, 9, 0)),
+            ("3h", local(2024, 3, 5, 12, 0)),
+            ("+ 30 min", local(2024, 3, 5, 9, 30)),
+            ("+1mo", local(2024, 4, 5, 9, 0)),
+            ("-1y", local(2023, 3, 5, 9, 0)),
+        ] {
+            let shift = Shift::parse(text).unwrap_or_else(|| panic!("{text} should be a shift"));
+            assert_eq!(shift.apply(due).unwrap(), shifted, "{text}");
+        }
+    }
+
+    #[test]
+    fn huge_shifts() {
+        let due = local(2024, 3, 5, 9, 0);
+        for text in ["+99999999d", "-4000000000w", "+4000000000h", "+400000000y", "-4000000000mo"] {
+            let shift = Shift::parse(text).unwrap_or_else(|| panic!("{text} should be a shift"));
+            assert!(shift.apply(due).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn dates_are_not_shifts() {
+        for text in [
+            "2024-03-05",
+            "5 Mar 2024",
+            "mar 5",
+            "9am",
+            "17:00",
+            "3pm",
+            "fri",
+            "tomorrow",
+            "3",
+            "",
+        ] {
+            assert_eq!(Shift::parse(text), None, "{text}");
+        }
+    }
+
+    /// The bulk due date prompt takes both shifts and dates
+    #[test]
+    fn shifts_and_dates_in_one_prompt() {
+        let now = local(2024, 3, 1, 12, 0);
+        for text in ["+2d", "-1w", "2024-03-05", "5 Mar 2024", "9am", "17:00", "3pm"] {
+            let understood =
+                Shift::parse(text).is_some() || parse_due_date(text, now).unwrap().is_some();
+            assert!(understood, "{text}");
+        }
+    }
+}
@@ -37,7 +37,7 @@ impl EditLabelsPopup {
         self
     }
 
-    pub fn text_editor_widget(&mut self) -> edtui::EditorView {
+    pub fn text_editor_widget(&mut self) -> edtui::EditorView<'_, '_> {
         EditorView::new(self.text_editor_mut())
     }
 
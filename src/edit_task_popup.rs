@@ -12,6 +12,8 @@ pub struct EditTaskPopup {
     text_editor: EditorState,
     focus: EditTaskFocus,
     task: Rc<RefCell<Task>>,
+    /// Why the last edit could not be applied
+    error: Option<String>,
 }
 
 /// Current focus of the Task Editor Popup
@@ -29,6 +31,7 @@ impl EditTaskPopup {
             text_editor: EditorState::default(),
             focus: EditTaskFocus::default(),
             task: Rc::clone(task),
+            error: None,
         }
     }
 
@@ -85,10 +88,36 @@ impl EditTaskPopup {
 
     pub fn load_text(&mut self, text: &str) -> &mut Self {
         self.text_editor = EditorState::new(Lines::from(text));
+        self.error = None;
         self
     }
 
-    pub fn text_editor_widget(&mut self) -> edtui::EditorView {
+    /// The text currently in the editor, with rows joined by newlines
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        let mut previous_row = 0;
+        self.text_editor.lines.iter().for_each(|(c, idx)| {
+            if idx.row > previous_row {
+                previous_row = idx.row;
+                text.push('\n');
+            }
+            if let Some(c) = c {
+                text.push(*c);
+            }
+        });
+        text
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn set_error(&mut self, error: Option<String>) -> &mut Self {
+        self.error = error;
+        self
+    }
+
+    pub fn text_editor_widget(&mut self) -> edtui::EditorView<'_, '_> {
         EditorView::new(self.text_editor_mut())
     }
 
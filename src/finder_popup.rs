@@ -0,0 +1,101 @@
+use crate::fuzzy::{fuzzy_match, FuzzyMatch};
+use crate::list_popup::ListPopup;
+use crate::task::Task;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Matches in the notes count for a bit less than matches in the title
+const NOTES_PENALTY: i64 = 8;
+
+/// Popup to fuzzy search the titles and notes of every task in the database, whatever the main
+/// view is showing
+#[derive(Debug, Default)]
+pub struct FinderPopup {
+    text: String,
+    tasks: Vec<Rc<RefCell<Task>>>,
+    results: ListPopup<FinderResult>,
+}
+
+/// A task matching the text typed into a [`FinderPopup`]
+#[derive(Debug)]
+pub struct FinderResult {
+    pub task: Rc<RefCell<Task>>,
+    /// Where the title matched, if it did
+    pub title: Option<FuzzyMatch>,
+    /// The line of the notes which matched best and where, when the title did not match as well
+    pub notes: Option<(String, FuzzyMatch)>,
+    score: i64,
+}
+
+impl FinderPopup {
+    /// Search the given tasks, starting with all of them listed
+    pub fn new(tasks: Vec<Rc<RefCell<Task>>>) -> Self {
+        let mut popup = Self {
+            tasks,
+            ..Self::default()
+        };
+        popup.search();
+        popup
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn push(&mut self, c: char) -> &mut Self {
+        self.text.push(c);
+        self.search();
+        self
+    }
+
+    pub fn pop(&mut self) -> &mut Self {
+        self.text.pop();
+        self.search();
+        self
+    }
+
+    /// Matching tasks, best first
+    pub fn results(&self) -> &ListPopup<FinderResult> {
+        &self.results
+    }
+
+    pub fn results_mut(&mut self) -> &mut ListPopup<FinderResult> {
+        &mut self.results
+    }
+
+    /// Match every task against the text again, going back to the best match
+    fn search(&mut self) {
+        let mut results = self
+            .tasks
+            .iter()
+            .filter_map(|task| {
+                let borrow = task.borrow();
+                let title = fuzzy_match(&self.text, borrow.title());
+                let notes = match self.text.trim().is_empty() {
+                    true => None,
+                    false => borrow
+                        .notes()
+                        .lines()
+                        .filter_map(|line| {
+                            Some((line.to_string(), fuzzy_match(&self.text, line)?))
+                        })
+                        .max_by_key(|(_, found)| found.score),
+                };
+                let title_score = title.as_ref().map(|found| found.score);
+                let notes_score = notes.as_ref().map(|(_, found)| found.score - NOTES_PENALTY);
+                let score = std::cmp::max(title_score, notes_score)?;
+                // only point at the notes when they are why the task is listed
+                let notes = notes.filter(|_| notes_score > title_score);
+                Some(FinderResult {
+                    task: Rc::clone(task),
+                    title,
+                    notes,
+                    score,
+                })
+            })
+            .collect::<Vec<_>>();
+        // stable, so equally good matches stay in the order of the database
+        results.sort_by_key(|result| std::cmp::Reverse(result.score));
+        self.results.set_items(results);
+    }
+}
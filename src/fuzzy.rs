@@ -0,0 +1,102 @@
+/// How well a pattern fuzzy matches some text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Higher is better
+    pub score: i64,
+    /// Indices of the characters of the text which matched the pattern
+    pub positions: Vec<usize>,
+}
+
+/// Bonus for each character of the pattern found
+const MATCH: i64 = 16;
+/// Bonus for a match right after the previous one, so that `dep` prefers `deploy` to `do epics`
+const CONSECUTIVE: i64 = 12;
+/// Bonus for a match at the start of a word
+const WORD_START: i64 = 10;
+/// Cost of each character skipped between the first and last match
+const GAP: i64 = 1;
+/// Cost of each character before the first match, up to `MAX_LEADING`
+const LEADING: i64 = 1;
+const MAX_LEADING: i64 = 10;
+
+/// Look for the characters of `pattern`, in order but not necessarily next to each other, in
+/// `text`. Case and spaces in the pattern are ignored. Every text matches an empty pattern.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let pattern = pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(lowercase)
+        .collect::<Vec<_>>();
+    let text = text.chars().map(lowercase).collect::<Vec<_>>();
+    let Some(&first) = pattern.first() else {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: vec![],
+        });
+    };
+
+    // try every place the first character could match, since the leftmost one is not always the
+    // best, like the `d` of `do deploy` when looking for `dep`
+    (0..text.len())
+        .filter(|&start| text[start] == first)
+        .filter_map(|start| match_from(&pattern, &text, start))
+        .max_by_key(|found| found.score)
+}
+
+/// Match the pattern greedily from `start`, scoring the result
+fn match_from(pattern: &[char], text: &[char], start: usize) -> Option<FuzzyMatch> {
+    let mut positions = Vec::with_capacity(pattern.len());
+    let mut idx = start;
+    for &c in pattern {
+        idx += text[idx..].iter().position(|&t| t == c)?;
+        positions.push(idx);
+        idx += 1;
+    }
+
+    let mut score = -std::cmp::min(start as i64, MAX_LEADING) * LEADING;
+    for (n, &position) in positions.iter().enumerate() {
+        score += MATCH;
+        if position == 0 || !text[position - 1].is_alphanumeric() {
+            score += WORD_START;
+        }
+        if n > 0 {
+            let previous = positions[n - 1];
+            match position - previous {
+                1 => score += CONSECUTIVE,
+                gap => score -= (gap as i64 - 1) * GAP,
+            }
+        }
+    }
+    Some(FuzzyMatch { score, positions })
+}
+
+/// One character for one, so that positions in the lowercase text are positions in the original
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(pattern: &str, text: &str) -> i64 {
+        fuzzy_match(pattern, text).unwrap().score
+    }
+
+    #[test]
+    fn matches() {
+        assert_eq!(fuzzy_match("dep", "do deploy").unwrap().positions, [3, 4, 5]);
+        assert_eq!(fuzzy_match("DEP", "Deploy").unwrap().positions, [0, 1, 2]);
+        assert_eq!(fuzzy_match("de ploy", "deploy").unwrap().positions, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(fuzzy_match("", "anything"), Some(FuzzyMatch { score: 0, positions: vec![] }));
+        assert_eq!(fuzzy_match("dpe", "deploy"), None);
+        assert_eq!(fuzzy_match("deploy", "dep"), None);
+    }
+
+    #[test]
+    fn ranking() {
+        assert!(score("dep", "deploy") > score("dep", "do epics"));
+        assert!(score("gs", "go shopping") > score("gs", "bugs"));
+        assert!(score("tax", "taxes") > score("tax", "file the taxes"));
+    }
+}
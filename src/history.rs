@@ -0,0 +1,88 @@
+use crate::task::TaskDB;
+use color_eyre::Result;
+use std::collections::VecDeque;
+
+/// The whole task database serialized to a string. The database is small enough that keeping full
+/// copies is simpler than tracking every kind of change separately.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot(String);
+
+impl Snapshot {
+    pub fn of(db: &TaskDB) -> Result<Self> {
+        Ok(Self(serde_json::to_string(db)?))
+    }
+
+    /// Rebuild the database the snapshot was taken of. Ids already handed out by `current` are
+    /// not handed out again, so that undoing an add and adding again gives a new id.
+    pub fn restore(&self, current: &TaskDB) -> Result<TaskDB> {
+        let mut db: TaskDB = serde_json::from_str(&self.0)?;
+        db.finish_loading();
+        db.keep_next_id(current);
+        Ok(db)
+    }
+}
+
+/// Undo and redo stacks for changes to the task database
+#[derive(Debug, Default)]
+pub struct History {
+    /// Oldest first, so the front is dropped once there are too many
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl History {
+    /// Number of undo steps which are remembered
+    const MAX_LEN: usize = 100;
+
+    /// Remember the state from before a change. Any redo steps are lost.
+    pub fn record(&mut self, before: Snapshot) {
+        self.undo.push_back(before);
+        if self.undo.len() > Self::MAX_LEN {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    /// Step back, given the current state. Returns the state to go back to.
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    /// Step forward again after an undo, given the current state. Returns the state to go to.
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current);
+        Some(next)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::Task;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn undone_ids_are_not_reused() {
+        let mut db = TaskDB::new();
+        let before = Snapshot::of(&db).unwrap();
+        let task = Rc::new(RefCell::new(Task::default()));
+        db.add_task(Rc::clone(&task));
+
+        let mut db = before.restore(&db).unwrap();
+        let again = Rc::new(RefCell::new(Task::default()));
+        db.add_task(Rc::clone(&again));
+        assert_ne!(task.borrow().id(), again.borrow().id());
+    }
+}
@@ -0,0 +1,74 @@
+/// A list shown in a popup with one item selected, moving around it loops back at either end
+#[derive(Debug)]
+pub struct ListPopup<T> {
+    items: Vec<T>,
+    selected_idx: usize,
+}
+
+impl<T> Default for ListPopup<T> {
+    fn default() -> Self {
+        Self {
+            items: vec![],
+            selected_idx: 0,
+        }
+    }
+}
+
+impl<T> ListPopup<T> {
+    /// List the given items, starting with the first one selected
+    pub fn new(items: Vec<T>) -> Self {
+        Self {
+            items,
+            selected_idx: 0,
+        }
+    }
+
+    /// Start with the item at `idx` selected, or the last one if there are not that many
+    pub fn with_selected(mut self, idx: usize) -> Self {
+        self.selected_idx = std::cmp::min(idx, self.items.len().saturating_sub(1));
+        self
+    }
+
+    /// Start with the first item matching selected, if any do
+    pub fn with_selected_where(mut self, matches: impl Fn(&T) -> bool) -> Self {
+        self.selected_idx = self.items.iter().position(matches).unwrap_or_default();
+        self
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Replace the items, going back to the top of the list
+    pub fn set_items(&mut self, items: Vec<T>) {
+        self.items = items;
+        self.selected_idx = 0;
+    }
+
+    pub fn selected_idx(&self) -> usize {
+        self.selected_idx
+    }
+
+    pub fn selected(&self) -> Option<&T> {
+        self.items.get(self.selected_idx)
+    }
+
+    /// Move down the list, looping back at the top if necessary
+    pub fn increment_selected(&mut self) -> &mut Self {
+        if !self.items.is_empty() {
+            self.selected_idx = (self.selected_idx + 1) % self.items.len();
+        }
+        self
+    }
+
+    /// Move up the list, looping back at the bottom if necessary
+    pub fn decrement_selected(&mut self) -> &mut Self {
+        if !self.items.is_empty() {
+            self.selected_idx = match self.selected_idx {
+                0 => self.items.len() - 1,
+                idx => idx - 1,
+            };
+        }
+        self
+    }
+}
@@ -7,17 +7,44 @@ pub mod task;
 pub mod edit_task_popup;
 pub mod task_view;
 pub mod edit_labels_popup;
+pub mod cli;
+pub mod storage;
+pub mod history;
+pub mod paths;
+pub mod prompt_popup;
+pub mod list_popup;
+pub mod open_database_popup;
+pub mod config;
+pub mod due_date;
+pub mod recurrence;
+pub mod query;
+pub mod sort;
+pub mod view_picker_popup;
+pub mod fuzzy;
+pub mod finder_popup;
+pub mod archive_popup;
 
 use venom::Venom;
+use clap::Parser;
+use cli::Cli;
+use config::Config;
 use color_eyre::Result;
 use event::{Event, EventHandler};
 use ratatui::{backend::CrosstermBackend, Terminal};
 use tui::Tui;
-use update::update;
+use update::{update, update_mouse};
 
 fn main() -> Result<()> {
+    // Subcommands work on the database directly and never open the terminal interface.
+    let cli = Cli::parse();
+    let save_path = paths::resolve_database(cli.file)?;
+    let config = Config::load(&paths::config_file()?)?;
+    if let Some(command) = cli.command {
+        return cli::run(command, save_path, config);
+    }
+
     // Create an application.
-    let mut app = Venom::new();
+    let mut app = Venom::new(save_path, config)?;
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(std::io::stderr());
@@ -32,9 +59,9 @@ fn main() -> Result<()> {
         tui.draw(&mut app)?;
         // Handle events.
         match tui.events.next()? {
-            Event::Tick => {}
+            Event::Tick => app.tick(),
             Event::Key(key_event) => update(&mut app, key_event),
-            Event::Mouse(_) => {}
+            Event::Mouse(mouse_event) => update_mouse(&mut app, mouse_event),
             Event::Resize(_, _) => {}
         };
     }
@@ -0,0 +1,5 @@
+use crate::list_popup::ListPopup;
+use std::path::PathBuf;
+
+/// Popup to pick which task database to work on
+pub type OpenDatabasePopup = ListPopup<PathBuf>;
@@ -0,0 +1,163 @@
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use std::path::{Path, PathBuf};
+
+/// Directory in the home directory used before venom followed the XDG layout. It keeps being used
+/// if it exists so that old task lists do not disappear.
+const LEGACY_DIR_STR: &str = ".venom";
+/// Name of the directory inside of the platform data directory
+const APP_DIR_STR: &str = "venom";
+/// Use this as the default save file name
+pub const DEFAULT_SAVE_FILE_STR: &str = "todo.json";
+/// Name of the database file inside of a project's `.venom` directory
+const PROJECT_DIR_FILE_STR: &str = "todo.json";
+/// Database file placed directly in a project directory
+const PROJECT_FILE_STR: &str = "venom.json";
+/// Name of the config file
+const CONFIG_FILE_STR: &str = "config.toml";
+/// File in the data directory which holds the path of the last database opened from the app
+const LAST_USED_FILE_STR: &str = "last_used";
+
+/// Directory which holds the task databases that are not given explicitly
+pub fn data_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir().ok_or_else(|| eyre!("could not find the home directory"))?;
+    let legacy = home.join(LEGACY_DIR_STR);
+    if legacy.is_dir() {
+        return Ok(legacy);
+    }
+    let data = dirs::data_dir().unwrap_or_else(|| home.join(".local").join("share"));
+    Ok(data.join(APP_DIR_STR))
+}
+
+/// Location of the config file. Like the databases it lives in the legacy directory if that exists.
+pub fn config_file() -> Result<PathBuf> {
+    let home = dirs::home_dir().ok_or_else(|| eyre!("could not find the home directory"))?;
+    let legacy = home.join(LEGACY_DIR_STR);
+    if legacy.is_dir() {
+        return Ok(legacy.join(CONFIG_FILE_STR));
+    }
+    let config = dirs::config_dir().unwrap_or_else(|| home.join(".config"));
+    Ok(config.join(APP_DIR_STR).join(CONFIG_FILE_STR))
+}
+
+/// Database to open when nothing else is asked for
+pub fn default_database() -> Result<PathBuf> {
+    Ok(data_dir()?.join(DEFAULT_SAVE_FILE_STR))
+}
+
+/// Pick the database to open. A path given on the command line or through `VENOM_FILE` wins,
+/// then a project database above the working directory, then whichever database was last opened
+/// from the app, then the default one.
+pub fn resolve_database(explicit: Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(path) = explicit {
+        return Ok(path);
+    }
+    if let Some(path) = std::env::current_dir()
+        .ok()
+        .and_then(|dir| find_project_database(&dir))
+    {
+        return Ok(path);
+    }
+    if let Some(path) = last_used_database() {
+        return Ok(path);
+    }
+    default_database()
+}
+
+/// Look for a `.venom/todo.json` or `venom.json` in `start` and each of its parents, the same way
+/// git finds its repository. The home directory is skipped since its `.venom` directory holds the
+/// global databases.
+pub fn find_project_database(start: &Path) -> Option<PathBuf> {
+    let home = dirs::home_dir();
+    start
+        .ancestors()
+        .filter(|dir| Some(*dir) != home.as_deref())
+        .flat_map(|dir| {
+            [
+                dir.join(LEGACY_DIR_STR).join(PROJECT_DIR_FILE_STR),
+                dir.join(PROJECT_FILE_STR),
+            ]
+        })
+        .find(|path| path.is_file())
+}
+
+/// Where `venom init` puts the database for a project rooted at `dir`
+pub fn project_database(dir: &Path) -> PathBuf {
+    dir.join(LEGACY_DIR_STR).join(PROJECT_DIR_FILE_STR)
+}
+
+/// Directory in which venom keeps its own files for a database, such as backups. That is the
+/// directory of the database if it is the data directory or a `.venom` directory, and otherwise a
+/// hidden `.venom` directory next to it so that a `venom.json` does not clutter its project.
+pub fn venom_dir_of(database: &Path) -> PathBuf {
+    let dir = database.parent().unwrap_or(Path::new(""));
+    let is_venom_dir = dir.file_name().is_some_and(|name| name == LEGACY_DIR_STR)
+        || data_dir().is_ok_and(|data| data == dir);
+    if is_venom_dir {
+        dir.to_path_buf()
+    } else {
+        dir.join(LEGACY_DIR_STR)
+    }
+}
+
+/// Short description of a database for the interface. Databases in the data directory go by their
+/// name, anything else by its path.
+pub fn display_name(path: &Path) -> String {
+    if let Ok(dir) = data_dir() {
+        if path.parent() == Some(dir.as_path()) {
+            if let Some(stem) = path.file_stem() {
+                return stem.to_string_lossy().to_string();
+            }
+        }
+    }
+    match dirs::home_dir().and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// The database last opened from the app, if it still exists
+pub fn last_used_database() -> Option<PathBuf> {
+    let file = data_dir().ok()?.join(LAST_USED_FILE_STR);
+    let path = PathBuf::from(std::fs::read_to_string(file).ok()?.trim());
+    path.is_file().then_some(path)
+}
+
+/// Remember a database to be opened next time
+pub fn remember_database(path: &Path) -> Result<()> {
+    let dir = data_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    let path = std::path::absolute(path)?;
+    std::fs::write(dir.join(LAST_USED_FILE_STR), path.to_string_lossy().as_bytes())
+        .wrap_err("could not remember the last used database")
+}
+
+/// Every database in the data directory
+pub fn databases() -> Vec<PathBuf> {
+    let Ok(dir) = data_dir() else {
+        return vec![];
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut databases = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    databases.sort();
+    databases
+}
+
+/// Path of a database in the data directory, adding the `.json` extension if needed
+pub fn database_named(name: &str) -> Result<PathBuf> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(std::path::is_separator) {
+        return Err(eyre!("\"{name}\" is not a valid database name"));
+    }
+    let file_name = if name.ends_with(".json") {
+        name.to_string()
+    } else {
+        format!("{name}.json")
+    };
+    Ok(data_dir()?.join(file_name))
+}
@@ -0,0 +1,89 @@
+/// What the text typed into a [`PromptPopup`] is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    /// Name of a new database to create in the data directory
+    NewDatabase,
+    /// Short names of the labels to filter the main view on
+    LabelFilter,
+    /// A [`crate::query::Query`] to filter the main view with
+    Query,
+    /// Name to save the current view settings under
+    SaveView,
+    /// A [`crate::sort::Sort`] for the main view
+    Sort,
+    /// Priority to give the marked tasks
+    Priority,
+    /// Labels to set, add or remove on the marked tasks
+    Labels,
+    /// Due date for the marked tasks, or an amount to move their due dates by
+    DueDate,
+}
+
+impl PromptKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::NewDatabase => "New Database",
+            Self::LabelFilter => "Filter Labels",
+            Self::Query => "Query",
+            Self::SaveView => "Save View As",
+            Self::Sort => "Sort By",
+            Self::Priority => "Set Priority",
+            Self::Labels => "Set Labels (+TAG adds, -TAG removes)",
+            Self::DueDate => "Set Due Date (or +2d, -1w to move it)",
+        }
+    }
+}
+
+/// Popup with a single line of text input
+#[derive(Debug)]
+pub struct PromptPopup {
+    kind: PromptKind,
+    text: String,
+    error: Option<String>,
+}
+
+impl PromptPopup {
+    pub fn new(kind: PromptKind) -> Self {
+        Self {
+            kind,
+            text: String::new(),
+            error: None,
+        }
+    }
+
+    /// Start with some text already typed in
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.text = text.to_string();
+        self
+    }
+
+    pub fn kind(&self) -> PromptKind {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn push(&mut self, c: char) -> &mut Self {
+        self.text.push(c);
+        self.error = None;
+        self
+    }
+
+    pub fn pop(&mut self) -> &mut Self {
+        self.text.pop();
+        self.error = None;
+        self
+    }
+
+    /// Problem with the current text, shown under the input
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn set_error(&mut self, error: &str) -> &mut Self {
+        self.error = Some(error.to_string());
+        self
+    }
+}
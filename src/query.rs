@@ -0,0 +1,446 @@
+use crate::due_date::parse_due_date;
+use crate::task::{Priority, Task, TaskId};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
+use color_eyre::eyre::{eyre, Result};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A filter typed into the `/` prompt, like `label:WORK priority>=medium due<1w !done`.
+///
+/// A query is a list of terms separated by spaces, and a task has to match every term to be
+/// shown. Any term can be negated with a leading `!` or `-`. The terms are:
+///
+/// - `done`, `overdue`, `repeats`, `subtask`
+/// - `label:WORK`, or `label:WORK,HOME` for either label, and `label:none`
+/// - `priority>=medium`, also with `<`, `<=`, `>`, `=` or `:`
+/// - `due<1w`, `due>=tomorrow`, `due:today` or `due:none`. The date can be anything the due date
+///   field understands, in quotes if it has spaces, or a bare amount of time like `3d`
+/// - `completed:today`, `completed:week` or `completed:month` for tasks done since the start of
+///   the day, week or month, and `completed>=3d` for tasks done in the last three days. Other
+///   dates are compared by day, so `completed<2024-03-05` means done before that day
+/// - `title~"deploy"` and `notes~word` for text contained in the title or notes
+/// - `id:12`
+/// - anything else is looked for in the title and notes
+///
+/// Dates are worked out each time the query is matched, so `due:today` moves on at midnight.
+#[derive(Debug, Clone)]
+pub struct Query {
+    text: String,
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone)]
+struct Term {
+    negated: bool,
+    condition: Condition,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Done,
+    Overdue,
+    Repeats,
+    Subtask,
+    /// Has at least one of these labels, or no labels at all if empty
+    Labels(Vec<String>),
+    Priority(Comparison, Priority),
+    /// The due date compared to this text as the due date field reads it
+    Due(Comparison, String),
+    NoDueDate,
+    /// The day the task was done compared to this day
+    Completed(Comparison, Since),
+    Title(String),
+    Notes(String),
+    Id(TaskId),
+    Text(String),
+}
+
+/// The day a `completed` term is compared to
+#[derive(Debug, Clone)]
+enum Since {
+    StartOfWeek,
+    StartOfMonth,
+    /// An amount of time like `3d` before today
+    Ago(String),
+    /// Any date the due date field understands
+    Day(String),
+}
+
+impl Since {
+    fn day(&self, now: DateTime<Local>) -> Result<NaiveDate> {
+        let today = now.date_naive();
+        match self {
+            Self::StartOfWeek => {
+                Ok(today - Duration::days(i64::from(today.weekday().num_days_from_monday())))
+            }
+            Self::StartOfMonth => Ok(today.with_day(1).unwrap_or(today)),
+            Self::Ago(amount) => {
+                let ahead = date(&format!("+{amount}"), now)?.date_naive() - today;
+                today
+                    .checked_sub_signed(ahead)
+                    .ok_or_else(|| eyre!("{amount} ago is out of range"))
+            }
+            Self::Day(text) => Ok(date(text, now)?.date_naive()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    /// Longest operators first so that `<=` is not read as `<`
+    const OPERATORS: [(&'static str, Self); 7] = [
+        ("<=", Self::LessOrEqual),
+        (">=", Self::GreaterOrEqual),
+        ("<", Self::Less),
+        (">", Self::Greater),
+        ("==", Self::Equal),
+        ("=", Self::Equal),
+        (":", Self::Equal),
+    ];
+
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Self::Less => ordering.is_lt(),
+            Self::LessOrEqual => ordering.is_le(),
+            Self::Equal => ordering.is_eq(),
+            Self::GreaterOrEqual => ordering.is_ge(),
+            Self::Greater => ordering.is_gt(),
+        }
+    }
+}
+
+impl Query {
+    /// Parse a query. Empty text gives `None`, which matches everything.
+    pub fn parse(text: &str) -> Result<Option<Self>> {
+        // dates are only checked here, and worked out again when matching
+        let now = Local::now();
+        let terms = tokenize(text)?
+            .iter()
+            .map(|token| parse_term(token, now))
+            .collect::<Result<Vec<_>>>()?;
+        if terms.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            text: text.trim().to_string(),
+            terms,
+        }))
+    }
+
+    /// The query as it was typed
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether the task matches every term of the query, with dates like `1w` counted from `now`
+    pub fn matches(&self, task: &Task, now: DateTime<Local>) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.condition.matches(task, now) != term.negated)
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Condition {
+    fn matches(&self, task: &Task, now: DateTime<Local>) -> bool {
+        match self {
+            Self::Done => task.is_done(),
+            Self::Overdue => !task.is_done() && task.due_date().is_some_and(|due| due < now),
+            Self::Repeats => task.recurrence().is_some(),
+            Self::Subtask => task.parent().is_some(),
+            Self::Labels(tags) if tags.is_empty() => task.labels().is_empty(),
+            Self::Labels(tags) => task.labels().iter().any(|label| {
+                let short_name = label.borrow().short_name_string();
+                tags.iter()
+                    .any(|tag| short_name.trim_end().eq_ignore_ascii_case(tag))
+            }),
+            Self::Priority(comparison, priority) => {
+                comparison.holds(task.priority().cmp(priority))
+            }
+            Self::Due(comparison, text) => match (task.due_date(), date(text, now)) {
+                // a day on its own means the whole day
+                (Some(due), Ok(date)) if *comparison == Comparison::Equal => {
+                    due.date_naive() == date.date_naive()
+                }
+                (Some(due), Ok(date)) => comparison.holds(due.cmp(&date)),
+                _ => false,
+            },
+            Self::NoDueDate => task.due_date().is_none(),
+            Self::Completed(comparison, since) => match (task.completed(), since.day(now)) {
+                (Some(completed), Ok(day)) => comparison.holds(completed.date_naive().cmp(&day)),
+                _ => false,
+            },
+            Self::Title(text) => contains(task.title(), text),
+            Self::Notes(text) => contains(task.notes(), text),
+            Self::Id(id) => task.id() == *id,
+            Self::Text(text) => contains(task.title(), text) || contains(task.notes(), text),
+        }
+    }
+}
+
+/// A date as the due date field reads it
+fn date(text: &str, now: DateTime<Local>) -> Result<DateTime<Local>> {
+    parse_due_date(text, now)?.ok_or_else(|| eyre!("\"{text}\" is not a date"))
+}
+
+fn contains(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// Split on spaces, keeping text in double quotes together and dropping the quotes
+fn tokenize(text: &str) -> Result<Vec<String>> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut in_quotes = false;
+    for c in text.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(eyre!("missing a closing quote"));
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_term(token: &str, now: DateTime<Local>) -> Result<Term> {
+    let (negated, token) = match token.strip_prefix(['!', '-']) {
+        Some(rest) if !rest.is_empty() => (true, rest),
+        _ => (false, token),
+    };
+    Ok(Term {
+        negated,
+        condition: parse_condition(token, now)?,
+    })
+}
+
+fn parse_condition(token: &str, now: DateTime<Local>) -> Result<Condition> {
+    let lower = token.to_lowercase();
+    match lower.as_str() {
+        "done" => return Ok(Condition::Done),
+        "overdue" => return Ok(Condition::Overdue),
+        "repeats" | "recurring" => return Ok(Condition::Repeats),
+        "subtask" => return Ok(Condition::Subtask),
+        _ => {}
+    }
+
+    if let Some((field, value)) = token.split_once('~') {
+        return match field.to_lowercase().as_str() {
+            "title" => Ok(Condition::Title(value.to_string())),
+            "notes" => Ok(Condition::Notes(value.to_string())),
+            _ => Err(eyre!("can not search for text in \"{field}\"")),
+        };
+    }
+
+    let Some((field, comparison, value)) = split_comparison(token) else {
+        return Ok(Condition::Text(token.to_string()));
+    };
+    let field = field.to_lowercase();
+    if value.is_empty() {
+        return Err(eyre!("\"{token}\" needs a value after the comparison"));
+    }
+    let equality_only = |condition| match comparison {
+        Comparison::Equal => Ok(condition),
+        _ => Err(eyre!("{field} can only be compared with \":\"")),
+    };
+
+    match field.as_str() {
+        "label" | "labels" | "l" => {
+            let tags = match value.to_lowercase().as_str() {
+                "none" => vec![],
+                _ => value.split(',').map(str::to_string).collect(),
+            };
+            equality_only(Condition::Labels(tags))
+        }
+        "priority" | "prio" | "p" => {
+            let priority = Priority::from_str(value).map_err(|_| {
+                eyre!("\"{value}\" is not a priority, use none, low, medium or high")
+            })?;
+            Ok(Condition::Priority(comparison, priority))
+        }
+        "due" => {
+            if value.eq_ignore_ascii_case("none") {
+                return equality_only(Condition::NoDueDate);
+            }
+            let text = match is_amount(value) {
+                true => format!("+{value}"),
+                false => value.to_string(),
+            };
+            date(&text, now)?;
+            Ok(Condition::Due(comparison, text))
+        }
+        "completed" | "finished" => {
+            let since = match value.to_lowercase().as_str() {
+                "week" => Since::StartOfWeek,
+                "month" => Since::StartOfMonth,
+                // a bare amount of time counts back from today rather than forward
+                _ if is_amount(value) => Since::Ago(value.to_string()),
+                _ => Since::Day(value.to_string()),
+            };
+            since.day(now)?;
+            match since {
+                Since::StartOfWeek | Since::StartOfMonth => {
+                    equality_only(Condition::Completed(Comparison::GreaterOrEqual, since))
+                }
+                _ => Ok(Condition::Completed(comparison, since)),
+            }
+        }
+        "id" => {
+            let id = value
+                .parse()
+                .map_err(|_| eyre!("\"{value}\" is not a task id"))?;
+            equality_only(Condition::Id(id))
+        }
+        _ => Err(eyre!("unknown field \"{field}\"")),
+    }
+}
+
+/// Whether a value is an amount of time like `3d` rather than a date
+fn is_amount(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_ascii_digit())
+        && value.ends_with(|c: char| c.is_ascii_alphabetic())
+}
+
+/// Split `priority>=medium` into the field, the comparison and the value
+fn split_comparison(token: &str) -> Option<(&str, Comparison, &str)> {
+    let start = token.find(['<', '>', '=', ':'])?;
+    let rest = &token[start..];
+    let (operator, comparison) = Comparison::OPERATORS
+        .iter()
+        .find(|(operator, _)| rest.starts_with(operator))?;
+    Some((&token[..start], *comparison, &rest[operator.len()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::TaskLabel;
+    use chrono::TimeZone;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn tasks() -> Vec<Task> {
+        let work = Rc::new(RefCell::new(TaskLabel::new("Work", "WORK", "")));
+        let home = Rc::new(RefCell::new(TaskLabel::new("Home", "HOME", "")));
+        let mut called = Task::builder()
+            .with_title("Call mom")
+            .with_labels(vec![Rc::clone(&home)])
+            .build();
+        called.toggle_done();
+        vec![
+            Task::builder()
+                .with_title("Deploy the site")
+                .with_notes("staging first")
+                .with_labels(vec![work])
+                .with_priority(Priority::High)
+                .with_due_date(Some(Local::now() + Duration::days(2)))
+                .build(),
+            Task::builder()
+                .with_title("Groceries")
+                .with_labels(vec![home])
+                .with_priority(Priority::Low)
+                .build(),
+            Task::builder()
+                .with_title("File taxes")
+                .with_priority(Priority::Medium)
+                .with_due_date(Local.with_ymd_and_hms(2020, 1, 1, 9, 0, 0).single())
+                .build(),
+            called,
+        ]
+    }
+
+    /// Titles of the tasks matching the query
+    fn matching(text: &str) -> Vec<String> {
+        let query = Query::parse(text).unwrap().unwrap();
+        tasks()
+            .iter()
+            .filter(|task| query.matches(task, Local::now()))
+            .map(|task| task.title().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn queries() {
+        for (text, titles) in [
+            (
+                r#"label:WORK priority>=medium due<1w !done title~"deploy""#,
+                vec!["Deploy the site"],
+            ),
+            ("label:work,home", vec!["Deploy the site", "Groceries", "Call mom"]),
+            ("label:none", vec!["File taxes"]),
+            ("priority>=medium", vec!["Deploy the site", "File taxes"]),
+            ("p<medium", vec!["Groceries", "Call mom"]),
+            ("due:none", vec!["Groceries", "Call mom"]),
+            ("due>=tomorrow", vec!["Deploy the site"]),
+            ("overdue", vec!["File taxes"]),
+            ("done", vec!["Call mom"]),
+            ("-done label:home", vec!["Groceries"]),
+            ("notes~staging", vec!["Deploy the site"]),
+            ("taxes", vec!["File taxes"]),
+            ("completed:today", vec!["Call mom"]),
+            ("completed>=3d", vec!["Call mom"]),
+        ] {
+            assert_eq!(matching(text), titles, "{text}");
+        }
+    }
+
+    #[test]
+    fn dates_follow_the_clock() {
+        let at = |day| Local.with_ymd_and_hms(2024, 3, day, 23, 59, 0).unwrap();
+        let task = Task::builder()
+            .with_due_date(Some(at(5)))
+            .with_completed(Some(at(4)))
+            .build();
+        for (text, matches_on_the_5th, matches_on_the_11th) in [
+            ("due:today", true, false),
+            ("due<1w", true, true),
+            ("completed:today", false, false),
+            ("completed:week", true, false),
+            ("completed>=3d", true, false),
+            ("completed:month", true, true),
+        ] {
+            let query = Query::parse(text).unwrap().unwrap();
+            assert_eq!(query.matches(&task, at(5)), matches_on_the_5th, "{text}");
+            assert_eq!(query.matches(&task, at(11)), matches_on_the_11th, "{text}");
+        }
+    }
+
+    #[test]
+    fn bad_queries() {
+        assert!(Query::parse("   ").unwrap().is_none());
+        for text in [
+            "priority>=urgent",
+            "colour:red",
+            r#"title~"unclosed"#,
+            "due<",
+            "due<someday",
+            "label<work",
+            "id:first",
+        ] {
+            assert!(Query::parse(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use crate::due_date::{checked_duration, DAY, WEEK};
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, Weekday};
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How often a task comes back after it is done
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Recurrence {
+    /// Every day
+    Daily,
+    /// Monday through Friday
+    Weekdays,
+    /// Every given number of weeks
+    Weeks(u32),
+    /// Every month on the given day, or the last day of shorter months
+    MonthlyOnDay(u32),
+    /// Every year on the same date
+    Yearly,
+    /// A given number of days after the task was last done, however late that was
+    AfterCompletion(u32),
+}
+
+impl Recurrence {
+    /// Most occurrences skipped over when catching up with a task which was done very late
+    const MAX_STEPS: usize = 10_000;
+    /// Longest gap between occurrences, in days
+    const MAX_DAYS: u32 = 10 * 366;
+
+    /// Parse rules like `daily`, `weekdays`, `weekly`, `every 2 weeks`, `monthly on 15`, `yearly`
+    /// or `3 days after completion`. Empty text means the task does not repeat. `monthly` without
+    /// a day uses the day of `due_date`.
+    pub fn parse(text: &str, due_date: Option<DateTime<Local>>) -> Result<Option<Self>> {
+        let text = text.trim().to_lowercase();
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        let number = words.iter().find_map(|word| {
+            word.trim_end_matches(|c: char| c.is_ascii_alphabetic())
+                .parse::<u32>()
+                .ok()
+        });
+
+        let recurrence = match words.as_slice() {
+            [] => return Ok(None),
+            ["daily"] | ["every", "day"] => Self::Daily,
+            ["weekdays"] | ["every", "weekday"] => Self::Weekdays,
+            ["weekly"] | ["every", "week"] => Self::Weeks(1),
+            ["biweekly"] | ["fortnightly"] => Self::Weeks(2),
+            ["yearly"] | ["annually"] | ["every", "year"] => Self::Yearly,
+            ["every", _, "week" | "weeks"] => {
+                let weeks = number
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| eyre!("the number of weeks needs to be a whole number"))?;
+                if weeks > Self::MAX_DAYS / 7 {
+                    return Err(eyre!("repeat at most every {} weeks", Self::MAX_DAYS / 7));
+                }
+                Self::Weeks(weeks)
+            }
+            _ if words.contains(&"after") && words.iter().any(|w| w.starts_with("day")) => {
+                let days = number
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| eyre!("say how many days after, like 3 days after"))?;
+                if days > Self::MAX_DAYS {
+                    return Err(eyre!("repeat at most {} days after", Self::MAX_DAYS));
+                }
+                Self::AfterCompletion(days)
+            }
+            _ if words[0] == "monthly" || words.contains(&"month") => {
+                let day = match number {
+                    Some(day) => day,
+                    None => due_date.unwrap_or_else(Local::now).day(),
+                };
+                if !(1..=31).contains(&day) {
+                    return Err(eyre!("there is no day {day} in a month"));
+                }
+                Self::MonthlyOnDay(day)
+            }
+            _ => return Err(eyre!("could not understand \"{text}\" as a repeat rule")),
+        };
+        Ok(Some(recurrence))
+    }
+
+    /// The due date of the occurrence after one which was done at `completed`. For fixed
+    /// schedules this is the first date on the schedule after both the old due date and the
+    /// completion, so that overdue occurrences do not pile up. Dates past the end of the calendar
+    /// are never reached, the occurrence stays on the last one there is instead.
+    pub fn next_due(
+        &self,
+        due_date: Option<DateTime<Local>>,
+        completed: DateTime<Local>,
+    ) -> DateTime<Local> {
+        let base = due_date.unwrap_or(completed);
+        if let Self::AfterCompletion(_) = self {
+            let date = completed.date_naive();
+            return at_time_of(self.step(date).unwrap_or(date), base);
+        }
+
+        let mut date = base.date_naive();
+        for _ in 0..Self::MAX_STEPS {
+            let Some(next) = self.step(date) else {
+                break;
+            };
+            date = next;
+            if at_time_of(date, base) > completed {
+                break;
+            }
+        }
+        at_time_of(date, base)
+    }
+
+    /// The next date on the schedule after `date`, if the calendar goes that far
+    fn step(&self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Self::Daily => date.succ_opt(),
+            Self::Weekdays => {
+                let mut next = date.succ_opt()?;
+                while matches!(next.weekday(), Weekday::Sat | Weekday::Sun) {
+                    next = next.succ_opt()?;
+                }
+                Some(next)
+            }
+            Self::Weeks(weeks) => date.checked_add_signed(checked_duration(*weeks, WEEK)?),
+            Self::MonthlyOnDay(day) => {
+                let next_month = date.with_day(1)?.checked_add_months(Months::new(1))?;
+                // clamp to the length of the month
+                (1..=*day).rev().find_map(|day| next_month.with_day(day))
+            }
+            Self::Yearly => date.checked_add_months(Months::new(12)),
+            Self::AfterCompletion(days) => date.checked_add_signed(checked_duration(*days, DAY)?),
+        }
+    }
+}
+
+/// `date` at the time of day of `time`
+fn at_time_of(date: NaiveDate, time: DateTime<Local>) -> DateTime<Local> {
+    date.and_time(time.time())
+        .and_local_timezone(Local)
+        .earliest()
+        .unwrap_or(time)
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Daily => write!(f, "daily"),
+            Self::Weekdays => write!(f, "weekdays"),
+            Self::Weeks(1) => write!(f, "weekly"),
+            Self::Weeks(weeks) => write!(f, "every {weeks} weeks"),
+            Self::MonthlyOnDay(day) => write!(f, "monthly on {day}"),
+            Self::Yearly => write!(f, "yearly"),
+            Self::AfterCompletion(1) => write!(f, "1 day after completion"),
+            Self::AfterCompletion(days) => write!(f, "{days} days after completion"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::due_date::tests::local;
+
+    #[test]
+    fn rules() {
+        let due = Some(local(2024, 3, 20, 9, 0));
+        for (text, recurrence) in [
+            ("daily", Recurrence::Daily),
+            ("every day", Recurrence::Daily),
+            ("weekdays", Recurrence::Weekdays),
+            ("weekly", Recurrence::Weeks(1)),
+            ("every 2 weeks", Recurrence::Weeks(2)),
+            ("biweekly", Recurrence::Weeks(2)),
+            ("monthly on 15", Recurrence::MonthlyOnDay(15)),
+            ("monthly", Recurrence::MonthlyOnDay(20)),
+            ("Yearly", Recurrence::Yearly),
+            ("3 days after completion", Recurrence::AfterCompletion(3)),
+        ] {
+            assert_eq!(Recurrence::parse(text, due).unwrap(), Some(recurrence), "{text}");
+        }
+        assert_eq!(Recurrence::parse("", due).unwrap(), None);
+        for text in [
+            "sometimes",
+            "every 0 weeks",
+            "every 600 weeks",
+            "monthly on 32",
+            "days after",
+            "4000000000 days after completion",
+        ] {
+            assert!(Recurrence::parse(text, due).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn rules_read_back_as_written() {
+        for recurrence in [
+            Recurrence::Daily,
+            Recurrence::Weekdays,
+            Recurrence::Weeks(1),
+            Recurrence::Weeks(3),
+            Recurrence::MonthlyOnDay(31),
+            Recurrence::Yearly,
+            Recurrence::AfterCompletion(1),
+            Recurrence::AfterCompletion(5),
+        ] {
+            let text = recurrence.to_string();
+            assert_eq!(Recurrence::parse(&text, None).unwrap(), Some(recurrence), "{text}");
+        }
+    }
+
+    #[test]
+    fn next_due() {
+        // a friday
+        let due = Some(local(2024, 1, 5, 9, 0));
+        let on_time = local(2024, 1, 5, 8, 0);
+        for (recurrence, completed, next) in [
+            (Recurrence::Daily, on_time, local(2024, 1, 6, 9, 0)),
+            (Recurrence::Weekdays, on_time, local(2024, 1, 8, 9, 0)),
+            (Recurrence::Weeks(2), on_time, local(2024, 1, 19, 9, 0)),
+            (Recurrence::Yearly, on_time, local(2025, 1, 5, 9, 0)),
+            // done late, so the missed days are skipped
+            (Recurrence::Daily, local(2024, 1, 9, 10, 0), local(2024, 1, 10, 9, 0)),
+            (Recurrence::AfterCompletion(3), local(2024, 1, 9, 10, 0), local(2024, 1, 12, 9, 0)),
+        ] {
+            assert_eq!(recurrence.next_due(due, completed), next, "{recurrence}");
+        }
+        // the end of shorter months
+        let due = Some(local(2024, 1, 31, 9, 0));
+        let next = Recurrence::MonthlyOnDay(31).next_due(due, local(2024, 1, 31, 8, 0));
+        assert_eq!(next, local(2024, 2, 29, 9, 0));
+    }
+}
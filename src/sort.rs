@@ -0,0 +1,269 @@
+use crate::task::{Task, TaskId};
+use color_eyre::eyre::{eyre, Report, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Order of the tasks in a view, written like `priority desc, due, undated first`.
+///
+/// Tasks are compared by each key in turn until one of them differs. Tasks which are equal on
+/// every key stay in their manual order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Sort {
+    keys: Vec<SortKey>,
+    /// Where tasks without a due date go when sorting by due date
+    undated: UndatedPosition,
+}
+
+/// One thing to sort by, and which way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    field: SortField,
+    descending: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum SortField {
+    Due,
+    Priority,
+    Title,
+    Label,
+    Created,
+    Modified,
+    Completed,
+    /// The order tasks are kept in in the database
+    Manual,
+}
+
+/// Where tasks without a due date are put when sorting by due date, whichever way that sort goes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum UndatedPosition {
+    First,
+    #[default]
+    Last,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Self {
+            keys: vec![SortKey {
+                field: SortField::Due,
+                descending: false,
+            }],
+            undated: UndatedPosition::default(),
+        }
+    }
+}
+
+impl Sort {
+    /// Compare two tasks. `positions` holds where each task is in the database, for the manual
+    /// order.
+    pub fn compare(&self, a: &Task, b: &Task, positions: &HashMap<TaskId, usize>) -> Ordering {
+        let position = |task: &Task| positions.get(&task.id()).copied();
+        for key in self.keys.iter() {
+            let ordering = match key.field {
+                // placed by `undated` rather than by the direction of the sort
+                SortField::Due => match (a.due_date(), b.due_date()) {
+                    (Some(a), Some(b)) => key.direct(a.cmp(&b)),
+                    (None, None) => Ordering::Equal,
+                    (None, Some(_)) => self.undated_first(),
+                    (Some(_), None) => self.undated_first().reverse(),
+                },
+                SortField::Priority => key.direct(a.priority().cmp(&b.priority())),
+                SortField::Title => {
+                    key.direct(a.title().to_lowercase().cmp(&b.title().to_lowercase()))
+                }
+                // tasks without labels go after those with labels
+                SortField::Label => match (first_label(a), first_label(b)) {
+                    (Some(a), Some(b)) => key.direct(a.cmp(&b)),
+                    (None, None) => Ordering::Equal,
+                    (None, Some(_)) => Ordering::Greater,
+                    (Some(_), None) => Ordering::Less,
+                },
+                SortField::Created => key.direct(a.created().cmp(&b.created())),
+                SortField::Modified => key.direct(a.modified().cmp(&b.modified())),
+                SortField::Completed => key.direct(a.completed().cmp(&b.completed())),
+                SortField::Manual => key.direct(position(a).cmp(&position(b))),
+            };
+            if ordering.is_ne() {
+                return ordering;
+            }
+        }
+        position(a).cmp(&position(b))
+    }
+
+    /// Whether tasks are shown in their manual order, and if so whether it is reversed
+    pub fn manual(&self) -> Option<bool> {
+        match self.keys.first() {
+            Some(key) if key.field == SortField::Manual => Some(key.descending),
+            _ => None,
+        }
+    }
+
+    fn undated_first(&self) -> Ordering {
+        match self.undated {
+            UndatedPosition::First => Ordering::Less,
+            UndatedPosition::Last => Ordering::Greater,
+        }
+    }
+}
+
+impl SortKey {
+    fn direct(&self, ordering: Ordering) -> Ordering {
+        match self.descending {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    }
+}
+
+fn first_label(task: &Task) -> Option<String> {
+    task.labels()
+        .first()
+        .map(|label| label.borrow().short_name_string().to_lowercase())
+}
+
+impl FromStr for SortField {
+    type Err = Report;
+
+    fn from_str(text: &str) -> Result<Self> {
+        Ok(match text.to_lowercase().as_str() {
+            "due" | "due_date" | "date" => Self::Due,
+            "priority" | "prio" => Self::Priority,
+            "title" | "name" => Self::Title,
+            "label" | "labels" => Self::Label,
+            "created" => Self::Created,
+            "modified" | "updated" => Self::Modified,
+            "completed" | "finished" => Self::Completed,
+            "manual" | "order" => Self::Manual,
+            _ => {
+                return Err(eyre!(
+                    "can not sort by \"{text}\", use due, priority, title, label, created, \
+                     modified, completed or manual"
+                ))
+            }
+        })
+    }
+}
+
+impl FromStr for Sort {
+    type Err = Report;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut sort = Self {
+            keys: vec![],
+            undated: UndatedPosition::default(),
+        };
+        for part in text.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let part = part.to_lowercase();
+            let words = part.split_whitespace().collect::<Vec<_>>();
+            match words.as_slice() {
+                ["undated", "first"] => sort.undated = UndatedPosition::First,
+                ["undated", "last"] => sort.undated = UndatedPosition::Last,
+                [field] | [field, "asc"] => sort.keys.push(SortKey {
+                    field: field.parse()?,
+                    descending: false,
+                }),
+                [field, "desc"] => sort.keys.push(SortKey {
+                    field: field.parse()?,
+                    descending: true,
+                }),
+                _ => return Err(eyre!("could not understand \"{part}\" as a sort order")),
+            }
+        }
+        if sort.keys.is_empty() {
+            return Err(eyre!("say what to sort by, like \"priority desc, due\""));
+        }
+        Ok(sort)
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for key in self.keys.iter() {
+            let direction = if key.descending { "desc" } else { "asc" };
+            write!(f, "{} {}, ", key.field, direction)?;
+        }
+        write!(f, "undated {}", self.undated)
+    }
+}
+
+impl TryFrom<String> for Sort {
+    type Error = Report;
+
+    fn try_from(text: String) -> Result<Self> {
+        text.parse()
+    }
+}
+
+impl From<Sort> for String {
+    fn from(sort: Sort) -> Self {
+        sort.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::Priority;
+    use chrono::{Local, TimeZone};
+
+    #[test]
+    fn sort_orders() {
+        for (text, shown) in [
+            ("due", "due asc, undated last"),
+            ("priority desc, due asc", "priority desc, due asc, undated last"),
+            ("prio desc, date, undated first", "priority desc, due asc, undated first"),
+            ("Title, created desc", "title asc, created desc, undated last"),
+            (
+                "label, modified, finished desc",
+                "label asc, modified asc, completed desc, undated last",
+            ),
+            ("manual", "manual asc, undated last"),
+        ] {
+            let sort = text.parse::<Sort>().unwrap();
+            assert_eq!(sort.to_string(), shown, "{text}");
+            assert_eq!(shown.parse::<Sort>().unwrap(), sort, "{shown}");
+        }
+        for text in ["", "undated first", "colour", "due sideways", "priority desc desc"] {
+            assert!(text.parse::<Sort>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn priority_then_due() {
+        let due = |day| Local.with_ymd_and_hms(2024, 3, day, 9, 0, 0).single();
+        let tasks = [
+            ("low", Priority::Low, due(1)),
+            ("high undated", Priority::High, None),
+            ("high late", Priority::High, due(9)),
+            ("high soon", Priority::High, due(2)),
+        ]
+        .map(|(title, priority, due)| {
+            Task::builder()
+                .with_title(title)
+                .with_priority(priority)
+                .with_due_date(due)
+                .build()
+        });
+        let sorted = |text: &str| {
+            let sort = text.parse::<Sort>().unwrap();
+            let mut sorted = tasks.iter().collect::<Vec<_>>();
+            sorted.sort_by(|a, b| sort.compare(a, b, &HashMap::new()));
+            sorted.iter().map(|task| task.title()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sorted("priority desc, due"),
+            ["high soon", "high late", "high undated", "low"]
+        );
+        assert_eq!(
+            sorted("priority desc, due desc, undated first"),
+            ["high undated", "high late", "high soon", "low"]
+        );
+    }
+}
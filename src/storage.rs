@@ -0,0 +1,365 @@
+use crate::paths;
+use crate::task::TaskDB;
+use chrono::{Local, NaiveDateTime};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Number of previous versions of a save file kept in the backup directory
+const BACKUPS_TO_KEEP: usize = 20;
+/// Name of the directory holding backups, which sits in [`paths::venom_dir_of`] the save file
+const BACKUP_DIR_STR: &str = "backups";
+/// Format of the time a backup was made, which is part of its file name
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+/// A step which upgrades a save file from one format version to the next. The step at index `n`
+/// turns version `n` into version `n + 1`.
+type Migration = fn(Value) -> Result<Value>;
+
+const MIGRATIONS: [Migration; TaskDB::FORMAT_VERSION as usize] = [labels_by_key, label_lists];
+
+/// Read a task database, upgrading it from older format versions as needed. A missing file is an
+/// empty database.
+///
+/// If the file exists but can not be understood, a copy is made next to it and an error is
+/// returned. Callers must not save over the file in that case.
+pub fn load(path: &Path) -> Result<TaskDB> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(TaskDB::new()),
+        Err(err) => return Err(err).wrap_err(format!("could not open {}", path.display())),
+    };
+
+    let parsed = serde_json::from_reader(BufReader::new(file))
+        .map_err(|err| eyre!(err))
+        .and_then(migrate)
+        .and_then(|value| Ok(serde_json::from_value::<TaskDB>(value)?));
+
+    match parsed {
+        Ok(mut db) => {
+            db.finish_loading();
+            Ok(db)
+        }
+        Err(err) => {
+            let backup = backup_unreadable(path)?;
+            Err(err.wrap_err(format!(
+                "could not read {}. It has been left untouched and a copy was saved to {}",
+                path.display(),
+                backup.display()
+            )))
+        }
+    }
+}
+
+/// Write a task database so that a crash part way through never leaves a half written file.
+///
+/// The data goes to a temporary file which is flushed to disk and then renamed over the old file.
+/// The old file is first copied into the backup directory, which only keeps the most recent
+/// [`BACKUPS_TO_KEEP`] copies. If that fails the database is still saved before the error is
+/// returned.
+pub fn save(db: &TaskDB, path: &Path) -> Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| eyre!("{} is not a file path", path.display()))?;
+    std::fs::create_dir_all(dir).wrap_err(format!("could not create {}", dir.display()))?;
+
+    let bytes = serde_json::to_vec_pretty(db)?;
+
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let write_tmp = || -> std::io::Result<()> {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&bytes)?;
+        file.sync_all()
+    };
+    if let Err(err) = write_tmp() {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err).wrap_err(format!("could not write {}", tmp_path.display()));
+    }
+
+    // a failed backup is reported once the new data is safely in place rather than losing it
+    let backed_up = if path.exists() { backup(path) } else { Ok(()) };
+
+    if let Err(err) = std::fs::rename(&tmp_path, path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err).wrap_err(format!(
+            "could not move {} to {}",
+            tmp_path.display(),
+            path.display()
+        ));
+    }
+
+    // make sure the rename itself is on disk. Directories can not be opened on every platform,
+    // in which case there is nothing more to do.
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    backed_up.wrap_err(format!(
+        "saved {}, but could not back up the previous version",
+        path.display()
+    ))
+}
+
+/// Copy the current save file into the backup directory and drop the oldest backups
+fn backup(path: &Path) -> Result<()> {
+    let dir = backup_dir(path);
+    std::fs::create_dir_all(&dir)?;
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = format!("{stem}-{}.json", Local::now().format(BACKUP_TIME_FORMAT));
+    std::fs::copy(path, dir.join(name))?;
+
+    let mut backups = std::fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|backup| {
+            backup
+                .file_name()
+                .is_some_and(|name| is_backup_of(&name.to_string_lossy(), &stem))
+        })
+        .collect::<Vec<_>>();
+    // the timestamps sort the same way as the names
+    backups.sort();
+    let excess = backups.len().saturating_sub(BACKUPS_TO_KEEP);
+    for old in backups.iter().take(excess) {
+        std::fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Whether a file in the backup directory is named `{stem}-{time}.json`. Only the time is allowed
+/// between the two, so that `todo.json` leaves the backups of `todo-work.json` alone.
+fn is_backup_of(name: &str, stem: &str) -> bool {
+    name.strip_prefix(stem)
+        .and_then(|name| name.strip_prefix('-'))
+        .and_then(|name| name.strip_suffix(".json"))
+        .is_some_and(|time| NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).is_ok())
+}
+
+/// Directory holding the backups of a save file
+pub fn backup_dir(path: &Path) -> PathBuf {
+    paths::venom_dir_of(path).join(BACKUP_DIR_STR)
+}
+
+/// Run every migration between the version of the file and the current one
+fn migrate(mut value: Value) -> Result<Value> {
+    let version = match value.get("format_version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| eyre!("format_version is not a number"))?,
+    };
+    if version > TaskDB::FORMAT_VERSION as u64 {
+        return Err(eyre!(
+            "the file has format version {} but this version of venom only understands up to {}",
+            version,
+            TaskDB::FORMAT_VERSION
+        ));
+    }
+
+    for (step, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        value = migration(value).wrap_err(format!("failed to upgrade from format version {step}"))?;
+        if let Some(object) = value.as_object_mut() {
+            object.insert("format_version".to_string(), (step + 1).into());
+        }
+    }
+    Ok(value)
+}
+
+/// Copy a file which could not be read so that it survives whatever happens next
+fn backup_unreadable(path: &Path) -> Result<PathBuf> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".unreadable-{}", Local::now().format("%Y%m%d-%H%M%S")));
+    let backup = path.with_file_name(name);
+    std::fs::copy(path, &backup)
+        .wrap_err(format!("could not back up {} to {}", path.display(), backup.display()))?;
+    Ok(backup)
+}
+
+/// 0 -> 1: tasks stop carrying a full copy of their label and only keep its short name
+fn labels_by_key(mut value: Value) -> Result<Value> {
+    let tasks = value
+        .get_mut("tasks")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten();
+    for task in tasks {
+        let Some(label) = task.get_mut("label") else {
+            continue;
+        };
+        if let Some(short_name) = label.get("short_name").and_then(Value::as_array) {
+            let key = short_name
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<String>()
+                .trim_end()
+                .to_string();
+            *label = Value::String(key);
+        }
+    }
+    Ok(value)
+}
+
+/// 1 -> 2: tasks can have any number of labels, so the single `label` becomes a `labels` list
+fn label_lists(mut value: Value) -> Result<Value> {
+    let tasks = value
+        .get_mut("tasks")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut);
+    for task in tasks {
+        let labels = match task.remove("label") {
+            Some(Value::String(key)) => vec![Value::String(key)],
+            _ => vec![],
+        };
+        task.insert("labels".to_string(), Value::Array(labels));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::rc::Rc;
+
+    /// A save file from before the format was versioned, when tasks held a copy of their label
+    fn version_0() -> Value {
+        let work = json!({
+            "short_name": ["W", "O", "R", "K"],
+            "long_name": "Work",
+            "color_str": "blue",
+        });
+        let task = |title: &str, label: Value| {
+            json!({
+                "title": title,
+                "priority": "High",
+                "notes": "",
+                "due_date": null,
+                "label": label,
+                "done": false,
+            })
+        };
+        json!({
+            "tasks": [task("labelled", work.clone()), task("unlabelled", Value::Null)],
+            "labels": [work],
+        })
+    }
+
+    /// Unique scratch directory for tests which touch the file system
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("venom-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn old_files_are_upgraded() {
+        let value = migrate(version_0()).unwrap();
+        assert_eq!(value["format_version"], TaskDB::FORMAT_VERSION);
+
+        let mut db = serde_json::from_value::<TaskDB>(value).unwrap();
+        db.finish_loading();
+        let tasks = db.tasks();
+        assert_eq!(tasks.len(), 2);
+        let labels = tasks[0].borrow().labels().to_vec();
+        assert_eq!(labels.len(), 1);
+        assert!(Rc::ptr_eq(&labels[0], &db.labels()[0]));
+        assert_eq!(labels[0].borrow().long_name(), "Work");
+        assert!(tasks[1].borrow().labels().is_empty());
+    }
+
+    #[test]
+    fn single_labels_become_lists() {
+        let value = json!({
+            "format_version": 1,
+            "tasks": [{ "label": "WORK" }, { "label": null }, {}],
+        });
+        let value = label_lists(value).unwrap();
+        let labels = value["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|task| (task.get("label"), task["labels"].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [(None, json!(["WORK"])), (None, json!([])), (None, json!([]))]
+        );
+    }
+
+    #[test]
+    fn current_files_are_left_alone() {
+        let value = serde_json::to_value(TaskDB::new()).unwrap();
+        assert_eq!(migrate(value.clone()).unwrap(), value);
+    }
+
+    #[test]
+    fn newer_files_are_refused() {
+        let value = json!({ "format_version": TaskDB::FORMAT_VERSION + 1, "tasks": [] });
+        assert!(migrate(value).is_err());
+    }
+
+    #[test]
+    fn unreadable_files_are_kept() {
+        let dir = scratch_dir("unreadable");
+        let path = dir.join("todo.json");
+        std::fs::write(&path, "{ not json").unwrap();
+
+        assert!(load(&path).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{ not json");
+        let copies = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().contains(".unreadable-"))
+            .count();
+        assert_eq!(copies, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_files_are_empty() {
+        let dir = scratch_dir("missing");
+        let db = load(&dir.join("todo.json")).unwrap();
+        assert!(db.tasks().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_backups_still_save() {
+        let dir = scratch_dir("failed-backup");
+        let path = dir.join("venom.json");
+        save(&TaskDB::new(), &path).unwrap();
+        // a file where the backup directory should go
+        std::fs::write(dir.join(".venom"), "").unwrap();
+
+        let mut db = TaskDB::new();
+        db.add_default();
+        assert!(save(&db, &path).is_err());
+        assert_eq!(load(&path).unwrap().num_tasks(), 1);
+        assert!(!dir.join("venom.json.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backups_of_each_database_are_kept_apart() {
+        assert!(is_backup_of("todo-20240305-091500.123.json", "todo"));
+        assert!(is_backup_of("todo-work-20240305-091500.123.json", "todo-work"));
+        assert!(!is_backup_of("todo-work-20240305-091500.123.json", "todo"));
+        assert!(!is_backup_of("todo-20240305-091500.123.json", "todo-work"));
+        assert!(!is_backup_of("todo-notes.json", "todo"));
+        assert!(!is_backup_of("todo-20240305-091500.123.json.tmp", "todo"));
+    }
+
+    #[test]
+    fn project_backups_are_hidden() {
+        let backups = Path::new("/project/.venom/backups");
+        assert_eq!(backup_dir(Path::new("/project/venom.json")), backups);
+        assert_eq!(backup_dir(Path::new("/project/.venom/todo.json")), backups);
+    }
+}
@@ -1,11 +1,16 @@
+use crate::due_date::{parse_due_date, Urgency};
+use crate::recurrence::Recurrence;
+use crate::task_view::ViewSpec;
 use crate::venom::EditableTaskProperty;
 use chrono::{DateTime, Local};
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
 use chrono::{Datelike, Timelike};
 use ratatui::{
     style::{Color, Style},
     text::{Line, Span, Text},
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::rc::Rc;
@@ -14,6 +19,9 @@ use std::str::FromStr;
 /// Data structure to keep track of the tasks and the labels attatched to them.
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct TaskDB {
+    /// Layout version of the save file. Files written before this existed read as 0.
+    #[serde(default)]
+    format_version: u32,
     /// list of all tasks. Tasks are stored as [`Rc<RefCell<Task>>`] for shared mutability - this
     /// might change in the future.
     tasks: Vec<Rc<RefCell<Task>>>,
@@ -21,19 +29,231 @@ pub struct TaskDB {
     /// is unlikely to change in the future. Lablels need to be editable from a task that holds
     /// them, and the app itself... i think.
     labels: Vec<Rc<RefCell<TaskLabel>>>,
+    /// The id handed out to the next task added to the database. Ids are never reused.
+    #[serde(default)]
+    next_id: TaskId,
+    /// Named views saved from the app
+    #[serde(default)]
+    views: Vec<ViewSpec>,
+    /// Finished tasks moved out of the way. They keep their ids and labels, but are not shown in
+    /// any view until they are restored.
+    #[serde(default)]
+    archive: Vec<Rc<RefCell<Task>>>,
 }
 
 impl TaskDB {
+    /// Layout version written by this build. Older files are upgraded by the migrations in
+    /// [`crate::storage`].
+    ///
+    /// - 0: every task holds a full copy of its label
+    /// - 1: tasks hold the short name of their label, which is linked back up on load
+    /// - 2: tasks hold a list of label short names instead of at most one
+    pub const FORMAT_VERSION: u32 = 2;
+
     pub fn new() -> Self {
-        Self::default()
+        Self {
+            format_version: Self::FORMAT_VERSION,
+            ..Default::default()
+        }
+    }
+
+    /// Finish setting up a freshly deserialized database. This links every task back to the
+    /// shared label of the same name and hands out ids to tasks which do not have one.
+    pub fn finish_loading(&mut self) {
+        self.link_labels();
+        self.assign_missing_ids();
+        self.unlink_bad_parents();
+    }
+
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    /// Point every task at the labels in [`TaskDB::labels`] with the same short names. Labels
+    /// which are not known to the database are dropped from the task.
+    pub fn link_labels(&self) {
+        for task in self.tasks.iter().chain(self.archive.iter()) {
+            let mut task = task.borrow_mut();
+            // not a change made by the user, so the modification time is left alone
+            task.labels = task
+                .labels()
+                .iter()
+                .filter_map(|label| self.label_by_tag(&label.borrow().short_name_string()))
+                .collect();
+        }
+    }
+
+    /// Give every task without an id a fresh one. Files written before tasks had ids are loaded
+    /// with all ids unset, so this needs to run after reading from disk.
+    pub fn assign_missing_ids(&mut self) {
+        let max_id = self
+            .tasks
+            .iter()
+            .chain(self.archive.iter())
+            .map(|task| task.borrow().id())
+            .max()
+            .unwrap_or_default();
+        self.next_id = std::cmp::max(self.next_id, max_id + 1);
+
+        let tasks = self.tasks.clone();
+        for task in tasks.iter() {
+            if task.borrow().id() == Task::NO_ID {
+                let id = self.take_next_id();
+                task.borrow_mut().set_id(id);
+            }
+        }
+    }
+
+    /// Turn subtasks whose parent is missing or which are their own ancestor back into top level
+    /// tasks, so that a hand edited file can not hide tasks or loop forever.
+    fn unlink_bad_parents(&mut self) {
+        for task in self.tasks.iter() {
+            let (id, parent) = {
+                let task = task.borrow();
+                (task.id(), task.parent())
+            };
+            let Some(parent) = parent else { continue };
+            if self.task_by_id(parent).is_none() || self.is_ancestor(id, parent) {
+                task.borrow_mut().parent = None;
+            }
+        }
+    }
+
+    /// Carry on from the next id of `other` if it is further along
+    pub fn keep_next_id(&mut self, other: &TaskDB) {
+        self.next_id = std::cmp::max(self.next_id, other.next_id);
+    }
+
+    fn take_next_id(&mut self) -> TaskId {
+        // ids start at one so that `Task::NO_ID` is never handed out
+        self.next_id = std::cmp::max(self.next_id, Task::NO_ID + 1);
+        let id = self.next_id;
+        self.next_id += 1;
+        id
     }
 
-    /// Remove a task by idx
+    /// Remove a task by pointer. Its subtasks move up to the parent of the removed task.
     pub fn remove_task(&mut self, task: &Rc<RefCell<Task>>) {
         let rm_idx = self.tasks.iter().position(|t| Rc::ptr_eq(t, task));
 
         if let Some(rm_idx) = rm_idx {
-            self.tasks.remove(rm_idx);
+            let removed = self.tasks.remove(rm_idx);
+            self.adopt_children(&removed.borrow());
+        }
+    }
+
+    /// Remove a task by id, returning it if it was in the database. Its subtasks move up to the
+    /// parent of the removed task.
+    pub fn remove_task_by_id(&mut self, id: TaskId) -> Option<Rc<RefCell<Task>>> {
+        let rm_idx = self.tasks.iter().position(|t| t.borrow().id() == id)?;
+        let removed = self.tasks.remove(rm_idx);
+        self.adopt_children(&removed.borrow());
+        Some(removed)
+    }
+
+    /// Move a task in the manual order so that it comes right before or right after another
+    pub fn move_task(&mut self, id: TaskId, next_to: TaskId, after: bool) {
+        let Some(from) = self.tasks.iter().position(|t| t.borrow().id() == id) else {
+            return;
+        };
+        let task = self.tasks.remove(from);
+        let Some(to) = self.tasks.iter().position(|t| t.borrow().id() == next_to) else {
+            self.tasks.insert(from, task);
+            return;
+        };
+        self.tasks.insert(if after { to + 1 } else { to }, task);
+    }
+
+    /// Hand the subtasks of a removed task over to its parent
+    fn adopt_children(&self, removed: &Task) {
+        for child in self.children(removed.id()) {
+            child.borrow_mut().parent = removed.parent();
+        }
+    }
+
+    /// The direct subtasks of a task, in database order
+    pub fn children(&self, id: TaskId) -> Vec<Rc<RefCell<Task>>> {
+        self.tasks
+            .iter()
+            .filter(|task| task.borrow().parent() == Some(id))
+            .cloned()
+            .collect()
+    }
+
+    /// The subtasks of a task, their subtasks and so on
+    pub fn descendants(&self, id: TaskId) -> Vec<Rc<RefCell<Task>>> {
+        let mut descendants = self.children(id);
+        let mut idx = 0;
+        while idx < descendants.len() {
+            let id = descendants[idx].borrow().id();
+            descendants.extend(self.children(id));
+            idx += 1;
+        }
+        descendants
+    }
+
+    /// Mark every subtask below a task as done
+    pub fn complete_descendants(&self, id: TaskId) {
+        for task in self.descendants(id) {
+            if !task.borrow().is_done() {
+                task.borrow_mut().toggle_done();
+            }
+        }
+    }
+
+    /// How many of the direct subtasks of a task are done, out of how many there are
+    pub fn progress(&self, id: TaskId) -> (usize, usize) {
+        let children = self.children(id);
+        let done = children.iter().filter(|task| task.borrow().is_done()).count();
+        (done, children.len())
+    }
+
+    /// Whether `ancestor` is `id` itself or any task above it
+    pub fn is_ancestor(&self, ancestor: TaskId, id: TaskId) -> bool {
+        let mut current = Some(id);
+        // bounded in case the file on disk has a loop in it
+        for _ in 0..=self.tasks.len() {
+            match current {
+                Some(current) if current == ancestor => return true,
+                Some(id) => current = self.task_by_id(id).and_then(|task| task.borrow().parent()),
+                None => return false,
+            }
+        }
+        false
+    }
+
+    /// Make a task a subtask of `parent`, or a top level task with `None`
+    pub fn set_parent(&self, id: TaskId, parent: Option<TaskId>) -> Result<()> {
+        let task = self
+            .task_by_id(id)
+            .ok_or_else(|| eyre!("there is no task with id {id}"))?;
+        if let Some(parent) = parent {
+            if self.task_by_id(parent).is_none() {
+                return Err(eyre!("there is no task with id {parent}"));
+            }
+            if self.is_ancestor(id, parent) {
+                return Err(eyre!("a task can not be a subtask of itself"));
+            }
+        }
+        let mut task = task.borrow_mut();
+        task.parent = parent;
+        task.touch();
+        Ok(())
+    }
+
+    /// Find a task by id
+    pub fn task_by_id(&self, id: TaskId) -> Option<Rc<RefCell<Task>>> {
+        self.tasks.iter().find(|t| t.borrow().id() == id).cloned()
+    }
+
+    /// Apply `f` to the task with the given id. Returns `false` if there is no such task.
+    pub fn update_task(&self, id: TaskId, f: impl FnOnce(&mut Task)) -> bool {
+        match self.task_by_id(id) {
+            Some(task) => {
+                f(&mut task.borrow_mut());
+                true
+            }
+            None => false,
         }
     }
 
@@ -45,16 +265,86 @@ impl TaskDB {
         self.labels.retain(|l| l.borrow().short_name() != tag);
 
         self.tasks
+            .iter()
+            .chain(self.archive.iter())
+            .for_each(|task| task.borrow_mut().remove_label(&tag));
+    }
+
+    /// Archived tasks, in the order they were archived
+    pub fn archive(&self) -> &[Rc<RefCell<Task>>] {
+        &self.archive
+    }
+
+    /// Move a finished task into the archive. Its subtasks move up to its parent, as when it is
+    /// removed.
+    pub fn archive_task(&mut self, id: TaskId) -> Result<()> {
+        let task = self
+            .task_by_id(id)
+            .ok_or_else(|| eyre!("there is no task with id {id}"))?;
+        if !task.borrow().is_done() {
+            return Err(eyre!("only finished tasks can be archived"));
+        }
+        self.remove_task(&task);
+        task.borrow_mut().parent = None;
+        self.archive.push(task);
+        Ok(())
+    }
+
+    /// Archive every finished task, or with `before` only those finished before then. Tasks with
+    /// no record of when they were finished are only archived without `before`. Returns how many
+    /// tasks were archived.
+    pub fn archive_done(&mut self, before: Option<DateTime<Local>>) -> usize {
+        let ids = self
+            .tasks
             .iter()
             .filter(|task| {
-                let binding = task.borrow();
-                let label = binding.label();
-                match label {
-                    Some(label) => label.borrow().short_name() == tag,
-                    None => false,
+                let task = task.borrow();
+                match before {
+                    _ if !task.is_done() => false,
+                    None => true,
+                    Some(before) => task.completed().is_some_and(|completed| completed < before),
                 }
             })
-            .for_each(|task| task.borrow_mut().remove_label());
+            .map(|task| task.borrow().id())
+            .collect::<Vec<_>>();
+        for id in ids.iter() {
+            let _ = self.archive_task(*id);
+        }
+        ids.len()
+    }
+
+    /// Move an archived task back into the task list, as a top level task
+    pub fn restore_task(&mut self, id: TaskId) -> Option<Rc<RefCell<Task>>> {
+        let idx = self.archive.iter().position(|t| t.borrow().id() == id)?;
+        let task = self.archive.remove(idx);
+        self.tasks.push(Rc::clone(&task));
+        Some(task)
+    }
+
+    /// Delete an archived task for good
+    pub fn remove_archived_task(&mut self, id: TaskId) -> Option<Rc<RefCell<Task>>> {
+        let idx = self.archive.iter().position(|t| t.borrow().id() == id)?;
+        Some(self.archive.remove(idx))
+    }
+
+    /// Named views saved from the app
+    pub fn views(&self) -> &[ViewSpec] {
+        &self.views
+    }
+
+    /// Save a named view, replacing any saved view with the same name
+    pub fn save_view(&mut self, view: ViewSpec) {
+        match self.views.iter_mut().find(|saved| saved.name == view.name) {
+            Some(saved) => *saved = view,
+            None => self.views.push(view),
+        }
+    }
+
+    /// Remove a saved view by name, returning whether there was one
+    pub fn remove_view(&mut self, name: &str) -> bool {
+        let len = self.views.len();
+        self.views.retain(|view| view.name != name);
+        self.views.len() != len
     }
 
     /// Labels list
@@ -85,6 +375,21 @@ impl TaskDB {
             .cloned()
     }
 
+    /// Look up each of the space separated short names in `tags`. Fails on the first one which
+    /// is not a known label.
+    pub fn labels_by_tags(&self, tags: &str) -> Result<Vec<Rc<RefCell<TaskLabel>>>> {
+        let mut labels: Vec<Rc<RefCell<TaskLabel>>> = vec![];
+        for tag in tags.split_whitespace() {
+            let label = self
+                .label_by_tag(tag)
+                .ok_or_else(|| eyre!("no label with the short name {tag}"))?;
+            if !labels.iter().any(|known| Rc::ptr_eq(known, &label)) {
+                labels.push(label);
+            }
+        }
+        Ok(labels)
+    }
+
     pub fn tasks(&self) -> &Vec<Rc<RefCell<Task>>> {
         &self.tasks
     }
@@ -109,8 +414,7 @@ impl TaskDB {
     }
 
     pub fn add_default(&mut self) -> &mut Self {
-        self.tasks.push(Task::default_rcc());
-        self
+        self.add_task(Task::default_rcc())
     }
 
     /// pull a task by cloning the [`Rc`] by index.
@@ -123,12 +427,20 @@ impl TaskDB {
 
     /// insert a task
     pub fn add_raw_task(&mut self, task: Task) -> &mut Self {
-        self.tasks.push(Rc::new(RefCell::new(task)));
-        self
+        self.add_task(Rc::new(RefCell::new(task)))
     }
 
-    /// insert a task
+    /// insert a task, giving it an id and creation time if it does not have them yet
     pub fn add_task(&mut self, task: Rc<RefCell<Task>>) -> &mut Self {
+        if task.borrow().id() == Task::NO_ID {
+            let id = self.take_next_id();
+            task.borrow_mut().set_id(id);
+        }
+        if task.borrow().created().is_none() {
+            let mut task = task.borrow_mut();
+            task.created = Some(Local::now());
+            task.modified = task.created;
+        }
         self.tasks.push(task);
         self
     }
@@ -163,26 +475,93 @@ impl TaskDB {
     }
 }
 
+/// Identifier of a task which stays the same for the lifetime of the task
+pub type TaskId = u64;
+
 /// Task that can be marked done or not
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Task {
+    /// Assigned by the [`TaskDB`] when the task is added
+    #[serde(default)]
+    id: TaskId,
     title: String,
     priority: Priority,
     notes: String,
     due_date: Option<DateTime<chrono::Local>>,
-    label: Option<Rc<RefCell<TaskLabel>>>,
+    /// Only the short names are saved, see [`TaskDB::finish_loading`]
+    #[serde(
+        default,
+        serialize_with = "serialize_label_keys",
+        deserialize_with = "deserialize_label_keys"
+    )]
+    labels: Vec<Rc<RefCell<TaskLabel>>>,
     done: bool,
+    /// Repeating tasks move on to their next due date instead of being marked done
+    #[serde(default)]
+    recurrence: Option<Recurrence>,
+    /// When each occurrence of a repeating task was done, oldest first
+    #[serde(default)]
+    completions: Vec<DateTime<Local>>,
+    /// The task this is a subtask of
+    #[serde(default)]
+    parent: Option<TaskId>,
+    /// Whether the subtasks are hidden in the main view
+    #[serde(default)]
+    collapsed: bool,
+    /// When the task was added. Unknown for tasks added before this was recorded.
+    #[serde(default)]
+    created: Option<DateTime<Local>>,
+    /// When the task was last changed
+    #[serde(default)]
+    modified: Option<DateTime<Local>>,
+    /// When the task was marked done, if it is done
+    #[serde(default)]
+    completed: Option<DateTime<Local>>,
+}
+
+fn serialize_label_keys<S: Serializer>(
+    labels: &[Rc<RefCell<TaskLabel>>],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    labels
+        .iter()
+        .map(|label| label.borrow().short_name_string().trim_end().to_string())
+        .collect::<Vec<_>>()
+        .serialize(serializer)
+}
+
+/// Produces placeholder labels holding only the short names. They get swapped for the shared
+/// labels once the whole database has been read.
+fn deserialize_label_keys<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Rc<RefCell<TaskLabel>>>, D::Error> {
+    let keys = Vec::<String>::deserialize(deserializer)?;
+    Ok(keys
+        .iter()
+        .map(|key| Rc::new(RefCell::new(TaskLabel::new("", key, ""))))
+        .collect())
 }
 
 impl Task {
+    /// Id of a task which has not been added to a [`TaskDB`] yet
+    pub const NO_ID: TaskId = 0;
+
     pub fn new(title: &str, priority: Priority) -> Self {
         Self {
+            id: Self::NO_ID,
             title: title.into(),
             priority,
             notes: "".to_string(),
             due_date: None,
-            label: None,
+            labels: vec![],
             done: false,
+            recurrence: None,
+            completions: vec![],
+            parent: None,
+            collapsed: false,
+            created: None,
+            modified: None,
+            completed: None,
         }
     }
 
@@ -190,8 +569,10 @@ impl Task {
         Rc::new(RefCell::new(Self::default()))
     }
 
-    pub fn remove_label(&mut self) {
-        self.label = None;
+    /// Take the label with the given short name off the task
+    pub fn remove_label(&mut self, short_name: &[char]) {
+        self.labels
+            .retain(|label| label.borrow().short_name() != short_name);
     }
 
     pub fn text_to_edit(&self, property: EditableTaskProperty) -> String {
@@ -202,13 +583,16 @@ impl Task {
             EditableTaskProperty::DueDate => {
                 format!("{} {}", self.date_string(), self.time_string())
             }
-            EditableTaskProperty::Label => match self.label() {
-                Some(label) => label.borrow().short_name().iter().collect(),
+            EditableTaskProperty::Repeat => match self.recurrence() {
+                Some(recurrence) => recurrence.to_string(),
                 None => "".to_string(),
             },
+            EditableTaskProperty::Labels => self.labels_string(),
         }
     }
-    pub fn set_property_from_str(&mut self, property: EditableTaskProperty, value: &str) {
+    /// Set a property from the text typed into the edit popup. Fails if the text does not make
+    /// sense for the property, in which case nothing changes.
+    pub fn set_property_from_str(&mut self, property: EditableTaskProperty, value: &str) -> Result<()> {
         match property {
             EditableTaskProperty::Title => {
                 self.set_title(value);
@@ -217,7 +601,11 @@ impl Task {
                 self.set_notes(value);
             }
             EditableTaskProperty::DueDate => {
-                self.set_date_str(value);
+                self.set_date_str(value)?;
+            }
+            EditableTaskProperty::Repeat => {
+                let recurrence = Recurrence::parse(value, self.due_date())?;
+                self.set_recurrence(recurrence);
             }
             EditableTaskProperty::Priority => {
                 match value {
@@ -238,31 +626,121 @@ impl Task {
             }
             _ => {}
         }
+        Ok(())
     }
 
     pub fn set_priority(&mut self, priority: Priority) -> &mut Self {
-        self.priority = priority;
+        if self.priority != priority {
+            self.priority = priority;
+            self.touch();
+        }
         self
     }
 
+    /// When the task was added to the database
+    pub fn created(&self) -> Option<DateTime<Local>> {
+        self.created
+    }
+
+    /// When the title, notes, due date, priority, labels, repeat rule, parent or doneness of the
+    /// task last changed
+    pub fn modified(&self) -> Option<DateTime<Local>> {
+        self.modified
+    }
+
+    /// When the task was marked done, or for a repeating task when it was last done
+    pub fn completed(&self) -> Option<DateTime<Local>> {
+        self.completed.or_else(|| self.completions.last().copied())
+    }
+
+    fn touch(&mut self) {
+        self.modified = Some(Local::now());
+    }
+
     pub fn builder() -> TaskBuilder {
         TaskBuilder::default()
     }
 
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn set_id(&mut self, id: TaskId) -> &mut Self {
+        self.id = id;
+        self
+    }
+
     pub fn title(&self) -> &str {
         &self.title
     }
 
-    pub fn label(&self) -> &Option<Rc<RefCell<TaskLabel>>> {
-        &self.label
+    pub fn labels(&self) -> &[Rc<RefCell<TaskLabel>>] {
+        &self.labels
+    }
+
+    /// The short names of the labels, separated by spaces
+    pub fn labels_string(&self) -> String {
+        self.labels
+            .iter()
+            .map(|label| label.borrow().short_name_string().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn has_label(&self, label: &Rc<RefCell<TaskLabel>>) -> bool {
+        self.labels.iter().any(|own| Rc::ptr_eq(own, label))
     }
 
     pub fn is_done(&self) -> bool {
         self.done
     }
 
+    /// Flip whether the task is done. Finishing a repeating task instead records the completion
+    /// and moves the task on to its next due date.
     pub fn toggle_done(&mut self) -> &mut Self {
-        self.done = !self.done;
+        match self.recurrence {
+            Some(recurrence) if !self.done => {
+                let now = Local::now();
+                self.completions.push(now);
+                self.due_date = Some(recurrence.next_due(self.due_date, now));
+            }
+            _ => {
+                self.done = !self.done;
+                self.completed = self.done.then(Local::now);
+            }
+        }
+        self.touch();
+        self
+    }
+
+    pub fn recurrence(&self) -> Option<Recurrence> {
+        self.recurrence
+    }
+
+    pub fn set_recurrence(&mut self, recurrence: Option<Recurrence>) -> &mut Self {
+        if self.recurrence != recurrence {
+            self.recurrence = recurrence;
+            self.touch();
+        }
+        self
+    }
+
+    /// When each occurrence of a repeating task was done, oldest first
+    pub fn completions(&self) -> &[DateTime<Local>] {
+        &self.completions
+    }
+
+    /// The task this is a subtask of. Use [`TaskDB::set_parent`] to change it.
+    pub fn parent(&self) -> Option<TaskId> {
+        self.parent
+    }
+
+    pub fn is_collapsed(&self) -> bool {
+        self.collapsed
+    }
+
+    pub fn toggle_collapsed(&mut self) -> &mut Self {
+        self.collapsed = !self.collapsed;
         self
     }
     pub fn priority(&self) -> Priority {
@@ -305,6 +783,15 @@ impl Task {
             }
         }
     }
+    /// How close the task is to being due at `now`. Tasks which are done or have no due date are
+    /// never urgent.
+    pub fn urgency(&self, now: DateTime<Local>, soon_days: u32) -> Option<Urgency> {
+        match (self.done, self.due_date) {
+            (false, Some(due)) => Some(Urgency::of(due, now, soon_days)),
+            _ => None,
+        }
+    }
+
     pub fn time_string(&self) -> String {
         if let Some(date) = self.due_date() {
             let time = date.time();
@@ -317,92 +804,69 @@ impl Task {
     }
 
     pub fn set_title(&mut self, title: &str) -> &mut Self {
-        self.title = title.to_string();
+        if self.title != title {
+            self.title = title.to_string();
+            self.touch();
+        }
         self
     }
 
-    pub fn set_label(&mut self, label: Option<Rc<RefCell<TaskLabel>>>) -> &mut Self {
-        self.label = label;
+    pub fn set_labels(&mut self, labels: Vec<Rc<RefCell<TaskLabel>>>) -> &mut Self {
+        let same = labels.len() == self.labels.len()
+            && labels.iter().zip(self.labels.iter()).all(|(a, b)| Rc::ptr_eq(a, b));
+        if !same {
+            self.labels = labels;
+            self.touch();
+        }
         self
     }
 
-    pub fn set_date_str(&mut self, date: &str) -> &mut Self {
-        let words = date.split_whitespace().collect::<Vec<_>>();
-
-        let num_words = words.len();
-
-        match num_words {
-            0 => self.set_no_date(),
-            4 => {
-                let day = words[0].parse::<u32>();
-                let month = match words[1] {
-                    "Jan" => Some(1),
-                    "Feb" => Some(2),
-                    "Mar" => Some(3),
-                    "Apr" => Some(4),
-                    "May" => Some(5),
-                    "Jun" => Some(6),
-                    "Jul" => Some(7),
-                    "Aug" => Some(8),
-                    "Sep" => Some(9),
-                    "Oct" => Some(10),
-                    "Nov" => Some(11),
-                    "Dec" => Some(12),
-                    _ => None,
-                };
-                let year = words[2].parse::<i32>();
-
-                let new_date = if day.is_err() || month.is_none() || year.is_err() {
-                    self.due_date().map(|date| date.date_naive())
-                } else {
-                    let day = day.unwrap();
-                    let month = month.unwrap();
-                    let year = year.unwrap();
-                    chrono::NaiveDate::from_ymd_opt(year, month, day)
-                };
-
-                let time = words[3].split(':').collect::<Vec<_>>();
-                let new_time = if time.len() != 2 {
-                    self.due_date().map(|date| date.time())
-                } else {
-                    let hour = time[0].parse::<u32>();
-                    let minute = time[1].parse::<u32>();
-
-                    if hour.is_err() || minute.is_err() {
-                        self.due_date().map(|date| date.time())
-                    } else {
-                        let hour = hour.unwrap();
-                        let minute = minute.unwrap();
-                        chrono::NaiveTime::from_hms_opt(hour, minute, 0)
-                    }
-                };
-
-                if new_date.is_some() && new_time.is_some() {
-                    let naive_dt = chrono::NaiveDateTime::new(new_date.unwrap(), new_time.unwrap());
-                    let date_time = naive_dt.and_local_timezone(Local).unwrap();
-                    self.set_date(&date_time)
-                } else {
-                    self
-                }
-            }
-            _ => self,
-        }
+    /// Set the due date from text like `tomorrow 5pm`, see [`parse_due_date`]. Empty text
+    /// removes the due date.
+    pub fn set_date_str(&mut self, date: &str) -> Result<&mut Self> {
+        Ok(match parse_due_date(date, Local::now())? {
+            Some(date) => self.set_date(&date),
+            None => self.set_no_date(),
+        })
     }
     pub fn set_date(&mut self, date: &DateTime<Local>) -> &mut Self {
-        self.due_date = Some(*date);
+        if self.due_date != Some(*date) {
+            self.due_date = Some(*date);
+            self.touch();
+        }
         self
     }
     pub fn set_no_date(&mut self) -> &mut Self {
-        self.due_date = None;
+        if self.due_date.is_some() {
+            self.due_date = None;
+            self.touch();
+        }
         self
     }
     pub fn set_notes(&mut self, notes: &str) -> &mut Self {
-        self.notes = notes.to_string();
+        if self.notes != notes {
+            self.notes = notes.to_string();
+            self.touch();
+        }
         self
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, strum::Display)]
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    strum::Display,
+    strum::EnumString,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum Priority {
     #[default]
     None,
@@ -412,15 +876,15 @@ pub enum Priority {
 }
 
 impl Priority {
-    pub fn as_text(&self) -> Text {
+    pub fn as_text(&self) -> Text<'_> {
         let (color, word) = self.formatting();
         Text::styled(word, Style::default().fg(color))
     }
-    pub fn as_span(&self) -> Span {
+    pub fn as_span(&self) -> Span<'_> {
         let (color, word) = self.formatting();
         Span::styled(word, Style::default().fg(color))
     }
-    pub fn as_line(&self) -> Line {
+    pub fn as_line(&self) -> Line<'_> {
         let (color, word) = self.formatting();
         Line::styled(word, Style::default().fg(color))
     }
@@ -473,8 +937,27 @@ impl TaskBuilder {
         self
     }
 
-    pub fn with_label(mut self, label: Option<Rc<RefCell<TaskLabel>>>) -> Self {
-        self.task.label = label;
+    /// Mark the task as done at the given time, or as not done with `None`
+    pub fn with_completed(mut self, completed: Option<DateTime<Local>>) -> Self {
+        self.task.done = completed.is_some();
+        self.task.completed = completed;
+        self
+    }
+
+    pub fn with_labels(mut self, labels: Vec<Rc<RefCell<TaskLabel>>>) -> Self {
+        self.task.labels = labels;
+        self
+    }
+
+    pub fn with_recurrence(mut self, recurrence: Option<Recurrence>) -> Self {
+        self.task.recurrence = recurrence;
+        self
+    }
+
+    /// Make the task a subtask of another. The parent is not checked until the task is added with
+    /// [`TaskDB::set_parent`].
+    pub fn with_parent(mut self, parent: Option<TaskId>) -> Self {
+        self.task.parent = parent;
         self
     }
 }
@@ -562,10 +1045,250 @@ impl TaskLabel {
     pub fn short_name_string(&self) -> String {
         self.short_name().iter().collect()
     }
-    pub fn as_span(&self) -> Span {
+    pub fn as_span(&self) -> Span<'_> {
         Span::styled(
             format!("{} ({})", self.long_name(), self.short_name_string()),
             Style::default().fg(self.color()),
         )
     }
 }
+
+/// A change to the labels of several tasks at once, as typed into the bulk label prompt
+#[derive(Debug, Clone)]
+pub struct LabelChange {
+    /// Labels which replace those of each task
+    replace: Option<Vec<Rc<RefCell<TaskLabel>>>>,
+    add: Vec<Rc<RefCell<TaskLabel>>>,
+    remove: Vec<Rc<RefCell<TaskLabel>>>,
+}
+
+impl LabelChange {
+    /// Short names on their own replace the labels, while `+TAG` adds a label and `-TAG` takes
+    /// one away. No short names at all clears the labels.
+    pub fn parse(text: &str, db: &TaskDB) -> Result<Self> {
+        let mut replace = None::<Vec<&str>>;
+        let mut add = vec![];
+        let mut remove = vec![];
+        for word in text.split_whitespace() {
+            if let Some(tag) = word.strip_prefix('+') {
+                add.push(tag);
+            } else if let Some(tag) = word.strip_prefix('-') {
+                remove.push(tag);
+            } else {
+                replace.get_or_insert_with(Vec::new).push(word);
+            }
+        }
+        if replace.is_none() && add.is_empty() && remove.is_empty() {
+            replace = Some(vec![]);
+        }
+        Ok(Self {
+            replace: match replace {
+                Some(tags) => Some(db.labels_by_tags(&tags.join(" "))?),
+                None => None,
+            },
+            add: db.labels_by_tags(&add.join(" "))?,
+            remove: db.labels_by_tags(&remove.join(" "))?,
+        })
+    }
+
+    /// The labels a task with `labels` ends up with
+    pub fn apply(&self, labels: &[Rc<RefCell<TaskLabel>>]) -> Vec<Rc<RefCell<TaskLabel>>> {
+        let mut labels = self.replace.clone().unwrap_or_else(|| labels.to_vec());
+        for label in self.add.iter() {
+            if !labels.iter().any(|other| Rc::ptr_eq(other, label)) {
+                labels.push(Rc::clone(label));
+            }
+        }
+        labels.retain(|label| !self.remove.iter().any(|other| Rc::ptr_eq(other, label)));
+        labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    /// A database with one task per title, with ids counting up from one
+    fn db_with(titles: &[&str]) -> TaskDB {
+        let mut db = TaskDB::new();
+        for title in titles {
+            db.add_raw_task(Task::new(title, Priority::default()));
+        }
+        db
+    }
+
+    fn order(db: &TaskDB) -> Vec<TaskId> {
+        db.tasks_iter().map(|task| task.borrow().id()).collect()
+    }
+
+    fn archived(db: &TaskDB) -> Vec<TaskId> {
+        db.archive().iter().map(|task| task.borrow().id()).collect()
+    }
+
+    /// Mark a task as done `days` days ago
+    fn finish(db: &TaskDB, id: TaskId, days: i64) {
+        db.update_task(id, |task| {
+            task.toggle_done();
+            task.completed = Some(Local::now() - Duration::days(days));
+        });
+    }
+
+    fn parent_of(db: &TaskDB, id: TaskId) -> Option<TaskId> {
+        db.task_by_id(id).unwrap().borrow().parent()
+    }
+
+    #[test]
+    fn subtasks_can_not_loop() {
+        let db = db_with(&["a", "b", "c"]);
+        db.set_parent(2, Some(1)).unwrap();
+        db.set_parent(3, Some(2)).unwrap();
+        assert!(db.is_ancestor(1, 3));
+        assert!(db.set_parent(1, Some(1)).is_err());
+        assert!(db.set_parent(1, Some(3)).is_err());
+        assert!(db.set_parent(1, Some(9)).is_err());
+        assert_eq!(parent_of(&db, 1), None);
+
+        db.set_parent(3, None).unwrap();
+        db.set_parent(1, Some(3)).unwrap();
+        assert_eq!(parent_of(&db, 1), Some(3));
+    }
+
+    #[test]
+    fn subtasks_of_removed_tasks_move_up() {
+        let mut db = db_with(&["a", "b", "c", "d"]);
+        db.set_parent(2, Some(1)).unwrap();
+        db.set_parent(3, Some(2)).unwrap();
+        db.set_parent(4, Some(2)).unwrap();
+
+        db.remove_task_by_id(2).unwrap();
+        assert_eq!(parent_of(&db, 3), Some(1));
+        assert_eq!(parent_of(&db, 4), Some(1));
+        let task = db.task_by_id(1).unwrap();
+        db.remove_task(&task);
+        assert_eq!(parent_of(&db, 3), None);
+        assert_eq!(parent_of(&db, 4), None);
+    }
+
+    #[test]
+    fn moving_tasks() {
+        let mut db = db_with(&["a", "b", "c", "d"]);
+        db.move_task(1, 3, true);
+        assert_eq!(order(&db), [2, 3, 1, 4]);
+        db.move_task(4, 2, false);
+        assert_eq!(order(&db), [4, 2, 3, 1]);
+        db.move_task(1, 4, false);
+        assert_eq!(order(&db), [1, 4, 2, 3]);
+        db.move_task(3, 3, true);
+        assert_eq!(order(&db), [1, 4, 2, 3]);
+        db.move_task(2, 9, true);
+        db.move_task(9, 2, true);
+        assert_eq!(order(&db), [1, 4, 2, 3]);
+    }
+
+    #[test]
+    fn archiving_tasks() {
+        let mut db = db_with(&["a", "b", "c"]);
+        db.set_parent(2, Some(1)).unwrap();
+        db.set_parent(3, Some(2)).unwrap();
+        assert!(db.archive_task(2).is_err());
+        assert!(db.archive_task(9).is_err());
+
+        finish(&db, 2, 0);
+        db.archive_task(2).unwrap();
+        assert_eq!(order(&db), [1, 3]);
+        assert_eq!(archived(&db), [2]);
+        assert_eq!(parent_of(&db, 3), Some(1));
+        assert_eq!(db.archive()[0].borrow().parent(), None);
+
+        // ids of archived tasks are not handed out again
+        db.add_default();
+        assert_eq!(order(&db), [1, 3, 4]);
+    }
+
+    #[test]
+    fn archiving_finished_tasks() {
+        let mut db = db_with(&["a", "b", "c", "d"]);
+        finish(&db, 1, 10);
+        finish(&db, 2, 1);
+        finish(&db, 3, 0);
+        db.update_task(3, |task| task.completed = None);
+
+        assert_eq!(db.archive_done(Some(Local::now() - Duration::days(7))), 1);
+        assert_eq!(archived(&db), [1]);
+        assert_eq!(db.archive_done(None), 2);
+        assert_eq!(archived(&db), [1, 2, 3]);
+        assert_eq!(order(&db), [4]);
+        assert_eq!(db.archive_done(None), 0);
+    }
+
+    #[test]
+    fn restoring_and_removing_archived_tasks() {
+        let mut db = db_with(&["a", "b", "c"]);
+        db.set_parent(2, Some(1)).unwrap();
+        finish(&db, 1, 0);
+        finish(&db, 2, 0);
+        db.archive_done(None);
+
+        assert!(db.restore_task(3).is_none());
+        db.restore_task(2).unwrap();
+        assert_eq!(order(&db), [3, 2]);
+        assert_eq!(parent_of(&db, 2), None);
+        assert!(db.task_by_id(2).unwrap().borrow().is_done());
+
+        assert!(db.remove_archived_task(2).is_none());
+        db.remove_archived_task(1).unwrap();
+        assert!(db.archive().is_empty());
+        assert_eq!(order(&db), [3, 2]);
+    }
+
+    #[test]
+    fn archived_tasks_keep_their_labels_and_ids() {
+        let mut db = db_with(&["a", "b"]);
+        db.add_raw_label(TaskLabel::new("Work", "work", "blue"));
+        let work = db.label_by_tag("work").unwrap();
+        db.update_task(1, |task| {
+            task.set_labels(vec![Rc::clone(&work)]);
+        });
+        finish(&db, 1, 0);
+        db.archive_done(None);
+
+        let json = serde_json::to_string(&db).unwrap();
+        let mut loaded: TaskDB = serde_json::from_str(&json).unwrap();
+        loaded.finish_loading();
+        let work = loaded.label_by_tag("work").unwrap();
+        assert!(loaded.archive()[0].borrow().has_label(&work));
+        loaded.add_default();
+        assert_eq!(order(&loaded), [2, 3]);
+
+        loaded.remove_label("work");
+        assert!(loaded.archive()[0].borrow().labels().is_empty());
+    }
+
+    #[test]
+    fn label_changes() {
+        let mut db = TaskDB::new();
+        for tag in ["work", "home", "fun"] {
+            db.add_raw_label(TaskLabel::new(tag, tag, ""));
+        }
+        let labels = db.labels_by_tags("work home").unwrap();
+        let tags = |text: &str| {
+            let change = LabelChange::parse(text, &db).unwrap();
+            change
+                .apply(&labels)
+                .iter()
+                .map(|label| label.borrow().short_name_string().trim_end().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tags("fun"), ["fun"]);
+        assert_eq!(tags("fun home"), ["fun", "home"]);
+        assert_eq!(tags("+fun"), ["work", "home", "fun"]);
+        assert_eq!(tags("+home"), ["work", "home"]);
+        assert_eq!(tags("-work"), ["home"]);
+        assert_eq!(tags("+fun -home"), ["work", "fun"]);
+        assert_eq!(tags("fun +work -fun"), ["work"]);
+        assert!(tags("").is_empty());
+        assert!(LabelChange::parse("+nope", &db).is_err());
+        assert!(LabelChange::parse("nope", &db).is_err());
+    }
+}
@@ -1,32 +1,254 @@
-use crate::task::{Task, TaskDB, TaskLabel};
-use chrono::Local;
+use crate::query::Query;
+use crate::sort::Sort;
+use crate::task::{Priority, Task, TaskDB, TaskId, TaskLabel};
+use chrono::{DateTime, Datelike, Duration, Local};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::{cell::RefCell, rc::Rc};
 //use itertools::Itertools;
 
-#[derive(Default)]
-enum CompletedTaskView {
+/// How tasks which are done are shown
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletedTaskView {
+    /// Shown after all of the tasks which are not done
     #[default]
+    #[serde(alias = "separate")]
     Seperate,
+    /// Shown mixed in with the other tasks
     Show,
+    /// Not shown
     Hide,
 }
 
-#[derive(Default)]
-enum SortOption {
+/// How many of the filtered labels a task needs to have to be shown
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelMatch {
+    /// At least one of them
+    #[default]
+    #[strum(to_string = "any of")]
+    Any,
+    /// Every one of them
+    #[strum(to_string = "all of")]
+    All,
+}
+
+/// What the rows of the main view are split into sections by
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    strum::Display,
+    strum::EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum GroupBy {
+    /// One flat list
     #[default]
-    DueDate,
+    None,
+    /// The first label of each task, in the order the labels are listed
+    Label,
+    /// Highest priority first
+    Priority,
+    /// Overdue, today, this week, later and no date
+    Due,
+    /// Tasks to do and then tasks which are done
+    Done,
+}
+
+impl GroupBy {
+    /// Where the group of a task goes among the other groups, and its title
+    fn key(
+        &self,
+        task: &Task,
+        labels: &[Rc<RefCell<TaskLabel>>],
+        now: DateTime<Local>,
+    ) -> (usize, String) {
+        match self {
+            Self::None => (0, String::new()),
+            Self::Label => match task.labels().first() {
+                Some(label) => {
+                    let position = labels.iter().position(|known| Rc::ptr_eq(known, label));
+                    (
+                        position.unwrap_or(labels.len()),
+                        label.borrow().long_name().to_string(),
+                    )
+                }
+                None => (labels.len() + 1, "No label".to_string()),
+            },
+            Self::Priority => (
+                match task.priority() {
+                    Priority::High => 0,
+                    Priority::Medium => 1,
+                    Priority::Low => 2,
+                    Priority::None => 3,
+                },
+                match task.priority() {
+                    Priority::None => "No priority".to_string(),
+                    priority => priority.to_string(),
+                },
+            ),
+            Self::Due => {
+                let today = now.date_naive();
+                let days_left = 7 - today.weekday().num_days_from_monday();
+                let next_week = today + Duration::days(i64::from(days_left));
+                match task.due_date() {
+                    None => (4, "No date".to_string()),
+                    Some(due) if due < now => (0, "Overdue".to_string()),
+                    Some(due) if due.date_naive() == today => (1, "Today".to_string()),
+                    Some(due) if due.date_naive() < next_week => (2, "This week".to_string()),
+                    Some(_) => (3, "Later".to_string()),
+                }
+            }
+            Self::Done => match task.is_done() {
+                false => (0, "To do".to_string()),
+                true => (1, "Done".to_string()),
+            },
+        }
+    }
+}
+
+/// A section of the main view. The rows of the group are `start..start + len` of
+/// [`TaskView::tasks`].
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub title: String,
+    pub start: usize,
+    pub len: usize,
+}
+
+/// A named set of view settings which can be switched to, either from `config.toml` or saved
+/// from the app into the task database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ViewSpec {
+    pub name: String,
+    /// See [`Query`]
+    #[serde(default)]
+    pub query: String,
+    /// Short names of the labels to filter on
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub label_match: LabelMatch,
+    #[serde(default)]
+    pub completed: CompletedTaskView,
+    #[serde(default)]
+    pub sort: Sort,
+    #[serde(default)]
+    pub group: GroupBy,
 }
 
 #[derive(Default)]
 pub struct TaskView {
+    /// Name of the [`ViewSpec`] last switched to, if any
+    name: Option<String>,
     completed_task_view: CompletedTaskView,
-    sort_option: SortOption,
-    current_label: Option<Rc<RefCell<TaskLabel>>>,
+    sort: Sort,
+    /// Only tasks with these labels are shown, or every task if this is empty
+    label_filter: Vec<Rc<RefCell<TaskLabel>>>,
+    label_match: LabelMatch,
+    /// Only tasks matching this are shown
+    query: Option<Query>,
     labels: Vec<Rc<RefCell<TaskLabel>>>,
     displayed_tasks: Vec<Rc<RefCell<Task>>>,
+    /// How many levels down the subtask tree each displayed task is
+    depths: Vec<usize>,
+    group_by: GroupBy,
+    /// Sections of the displayed tasks, empty unless grouping
+    groups: Vec<Group>,
 }
 
 impl TaskView {
+    /// Take on the settings of a named view. Nothing changes if the query or labels of the view
+    /// are not valid for the database.
+    pub fn apply(&mut self, spec: &ViewSpec, db: &TaskDB) -> Result<()> {
+        let query = Query::parse(&spec.query)?;
+        let labels = db.labels_by_tags(&spec.labels.join(" "))?;
+        self.name = Some(spec.name.clone());
+        self.query = query;
+        self.label_filter = labels;
+        self.label_match = spec.label_match;
+        self.completed_task_view = spec.completed;
+        self.sort = spec.sort.clone();
+        self.group_by = spec.group;
+        Ok(())
+    }
+
+    /// Go back to showing every task, with completed tasks shown the given way
+    pub fn reset(&mut self, completed_task_view: CompletedTaskView) {
+        *self = Self {
+            completed_task_view,
+            ..Self::default()
+        };
+    }
+
+    /// The current settings as a view with the given name
+    pub fn spec(&self, name: &str) -> ViewSpec {
+        ViewSpec {
+            name: name.to_string(),
+            query: self.query.as_ref().map(Query::text).unwrap_or_default().to_string(),
+            labels: self
+                .label_filter
+                .iter()
+                .map(|label| label.borrow().short_name_string().trim_end().to_string())
+                .collect(),
+            label_match: self.label_match,
+            completed: self.completed_task_view,
+            sort: self.sort.clone(),
+            group: self.group_by,
+        }
+    }
+
+    /// Name of the view last switched to. Changing a setting afterwards does not clear it.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn set_name(&mut self, name: Option<String>) {
+        self.name = name;
+    }
+
+    /// Step through the ways of grouping tasks
+    pub fn toggle_group_by(&mut self) {
+        self.group_by = match self.group_by {
+            GroupBy::None => GroupBy::Label,
+            GroupBy::Label => GroupBy::Priority,
+            GroupBy::Priority => GroupBy::Due,
+            GroupBy::Due => GroupBy::Done,
+            GroupBy::Done => GroupBy::None,
+        }
+    }
+
+    pub fn set_group_by(&mut self, group_by: GroupBy) {
+        self.group_by = group_by;
+    }
+
+    pub fn group_by(&self) -> GroupBy {
+        self.group_by
+    }
+
+    /// Sections of the displayed tasks, empty unless grouping
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
+    pub fn set_sort(&mut self, sort: Sort) {
+        self.sort = sort;
+    }
+
+    pub fn sort(&self) -> &Sort {
+        &self.sort
+    }
+
     pub fn toggle_completed_tasks(&mut self) {
         self.completed_task_view = match self.completed_task_view {
             CompletedTaskView::Seperate => CompletedTaskView::Show,
@@ -35,10 +257,15 @@ impl TaskView {
         }
     }
 
+    pub fn set_completed_task_view(&mut self, completed_task_view: CompletedTaskView) {
+        self.completed_task_view = completed_task_view;
+    }
+
+    /// Step through filtering on each label by itself, and then on no label at all
     pub fn toggle_selected_label(&mut self) {
-        self.current_label = match &self.current_label {
-            None => self.labels.first().map(Rc::clone),
-            Some(label) => {
+        let next = match self.label_filter.as_slice() {
+            [] => self.labels.first().map(Rc::clone),
+            [label] => {
                 let idx = self
                     .labels
                     .iter()
@@ -54,47 +281,87 @@ impl TaskView {
                     }
                 }
             }
+            _ => None,
+        };
+        self.label_filter = next.into_iter().collect();
+    }
+
+    /// Only show tasks with these labels, see [`TaskView::toggle_label_match`]
+    pub fn set_label_filter(&mut self, labels: Vec<Rc<RefCell<TaskLabel>>>) {
+        self.label_filter = labels;
+    }
+
+    pub fn label_filter(&self) -> &[Rc<RefCell<TaskLabel>>] {
+        &self.label_filter
+    }
+
+    /// Switch between showing tasks with any of the filtered labels and all of them
+    pub fn toggle_label_match(&mut self) {
+        self.label_match = match self.label_match {
+            LabelMatch::Any => LabelMatch::All,
+            LabelMatch::All => LabelMatch::Any,
         }
     }
 
-    pub fn generate_displayed_list(&mut self, db: &TaskDB) {
+    pub fn set_label_match(&mut self, label_match: LabelMatch) {
+        self.label_match = label_match;
+    }
+
+    pub fn label_match(&self) -> LabelMatch {
+        self.label_match
+    }
+
+    /// Only show tasks matching the query, or every task with `None`
+    pub fn set_query(&mut self, query: Option<Query>) {
+        self.query = query;
+    }
+
+    pub fn query(&self) -> Option<&Query> {
+        self.query.as_ref()
+    }
+
+    pub fn generate_displayed_list(&mut self, db: &TaskDB, now: DateTime<Local>) {
+        self.labels = db.labels().clone();
+        self.label_filter = self
+            .label_filter
+            .iter()
+            .filter_map(|label| {
+                self.labels
+                    .iter()
+                    .find(|&l| l.borrow().short_name() == label.borrow().short_name())
+                    .cloned()
+            })
+            .collect();
+
         self.displayed_tasks = db
             .tasks_iter()
             .filter(|task| match self.completed_task_view {
                 CompletedTaskView::Hide => !task.borrow().is_done(),
                 _ => true,
             })
-            .filter(|task| match &self.current_label {
-                None => true,
-                Some(label) => {
-                    if let Some(task_label) = task.borrow().label() {
-                        //todo: make this work at the pointer level
-                        //Rc::ptr_eq(task_label, label)
-                        task_label.borrow().short_name() == label.borrow().short_name()
-                    } else {
-                        false
-                    }
+            .filter(|task| {
+                let task = task.borrow();
+                let mut filter = self.label_filter.iter();
+                match self.label_match {
+                    _ if self.label_filter.is_empty() => true,
+                    LabelMatch::Any => filter.any(|label| task.has_label(label)),
+                    LabelMatch::All => filter.all(|label| task.has_label(label)),
                 }
             })
+            .filter(|task| match &self.query {
+                None => true,
+                Some(query) => query.matches(&task.borrow(), now),
+            })
             .cloned()
             .collect();
 
-        self.labels = db.labels().clone();
-        self.current_label = match &self.current_label {
-            None => None,
-            Some(label) => self
-                .labels
-                .iter()
-                .find(|&l| l.borrow().short_name() == label.borrow().short_name())
-                .cloned(),
-        };
-
-        match self.sort_option {
-            SortOption::DueDate => {
-                self.displayed_tasks
-                    .sort_by_key(|task| task.borrow().due_date().unwrap_or(Local::now()));
-            }
-        }
+        let positions = db
+            .tasks_iter()
+            .enumerate()
+            .map(|(idx, task)| (task.borrow().id(), idx))
+            .collect::<HashMap<_, _>>();
+        self.displayed_tasks
+            .sort_by(|a, b| self.sort.compare(&a.borrow(), &b.borrow(), &positions));
 
         if let CompletedTaskView::Seperate = self.completed_task_view {
             let (v1, v2): (Vec<_>, Vec<_>) = self
@@ -105,6 +372,95 @@ impl TaskView {
             self.displayed_tasks = v1;
             self.displayed_tasks.extend(v2);
         }
+
+        self.arrange_subtasks();
+        self.arrange_groups(now);
+    }
+
+    /// Gather the top level tasks, along with their subtasks, into groups. The order within each
+    /// group stays the same.
+    fn arrange_groups(&mut self, now: DateTime<Local>) {
+        self.groups.clear();
+        if self.group_by == GroupBy::None {
+            return;
+        }
+        let tasks = std::mem::take(&mut self.displayed_tasks);
+        let depths = std::mem::take(&mut self.depths);
+
+        // each top level task along with the subtasks shown under it
+        type Block = ((usize, String), Vec<(Rc<RefCell<Task>>, usize)>);
+        let mut blocks: Vec<Block> = vec![];
+        for (task, depth) in tasks.into_iter().zip(depths) {
+            if depth == 0 || blocks.is_empty() {
+                let key = self.group_by.key(&task.borrow(), &self.labels, now);
+                blocks.push((key, vec![]));
+            }
+            if let Some((_, rows)) = blocks.last_mut() {
+                rows.push((task, depth));
+            }
+        }
+        blocks.sort_by_key(|((order, _), _)| *order);
+
+        for ((_, title), rows) in blocks {
+            if self.groups.last().map(|group| &group.title) != Some(&title) {
+                self.groups.push(Group {
+                    title,
+                    start: self.displayed_tasks.len(),
+                    len: 0,
+                });
+            }
+            if let Some(group) = self.groups.last_mut() {
+                group.len += rows.len();
+            }
+            for (task, depth) in rows {
+                self.displayed_tasks.push(task);
+                self.depths.push(depth);
+            }
+        }
+    }
+
+    /// Put subtasks right under their parent, keeping the sorted order among siblings. Subtasks of
+    /// collapsed tasks are left out, and subtasks whose parent is filtered out are shown at the
+    /// top level.
+    fn arrange_subtasks(&mut self) {
+        let shown = self
+            .displayed_tasks
+            .iter()
+            .map(|task| task.borrow().id())
+            .collect::<HashSet<TaskId>>();
+        let sorted = std::mem::take(&mut self.displayed_tasks);
+        self.depths.clear();
+        for task in sorted.iter() {
+            let is_root = match task.borrow().parent() {
+                Some(parent) => !shown.contains(&parent),
+                None => true,
+            };
+            if is_root {
+                self.push_with_subtasks(task, 0, &sorted);
+            }
+        }
+    }
+
+    fn push_with_subtasks(
+        &mut self,
+        task: &Rc<RefCell<Task>>,
+        depth: usize,
+        sorted: &[Rc<RefCell<Task>>],
+    ) {
+        self.displayed_tasks.push(Rc::clone(task));
+        self.depths.push(depth);
+        let (id, collapsed) = (task.borrow().id(), task.borrow().is_collapsed());
+        if collapsed {
+            return;
+        }
+        for child in sorted.iter().filter(|t| t.borrow().parent() == Some(id)) {
+            self.push_with_subtasks(child, depth + 1, sorted);
+        }
+    }
+
+    /// How many levels down the subtask tree the displayed task at `idx` is
+    pub fn depth(&self, idx: usize) -> usize {
+        self.depths.get(idx).copied().unwrap_or_default()
     }
 
     pub fn num_tasks(&self) -> usize {
@@ -120,6 +476,87 @@ impl TaskView {
     }
 
     pub fn has_no_tasks(&self) -> bool {
-        return self.tasks().is_empty();
+        self.tasks().is_empty()
+    }
+
+    pub fn has_tasks(&self) -> bool {
+        !self.has_no_tasks()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn grouped(db: &TaskDB, group_by: GroupBy) -> (Vec<String>, Vec<(String, usize, usize)>) {
+        let mut view = TaskView::default();
+        view.set_sort("manual".parse().unwrap());
+        view.set_group_by(group_by);
+        let now = Local.with_ymd_and_hms(2024, 3, 6, 12, 0, 0).unwrap();
+        view.generate_displayed_list(db, now);
+        let titles = view
+            .tasks()
+            .iter()
+            .map(|task| task.borrow().title().to_string())
+            .collect();
+        let groups = view
+            .groups()
+            .iter()
+            .map(|group| (group.title.clone(), group.start, group.len))
+            .collect();
+        (titles, groups)
+    }
+
+    #[test]
+    fn groups_keep_subtasks_with_their_parent() {
+        let due = |month, day, hour| Local.with_ymd_and_hms(2024, month, day, hour, 0, 0).single();
+        let mut db = TaskDB::new();
+        for (title, priority, due_date) in [
+            ("later", Priority::High, due(4, 1, 9)),
+            ("overdue", Priority::Low, due(3, 1, 9)),
+            ("subtask", Priority::None, due(3, 1, 9)),
+            ("undated", Priority::Medium, None),
+            ("today", Priority::None, due(3, 6, 18)),
+            ("friday", Priority::None, due(3, 8, 9)),
+        ] {
+            db.add_raw_task(
+                Task::builder()
+                    .with_title(title)
+                    .with_priority(priority)
+                    .with_due_date(due_date)
+                    .build(),
+            );
+        }
+        db.set_parent(3, Some(1)).unwrap();
+
+        let (titles, groups) = grouped(&db, GroupBy::Due);
+        assert_eq!(titles, ["overdue", "today", "friday", "later", "subtask", "undated"]);
+        assert_eq!(
+            groups,
+            [
+                ("Overdue".to_string(), 0, 1),
+                ("Today".to_string(), 1, 1),
+                ("This week".to_string(), 2, 1),
+                ("Later".to_string(), 3, 2),
+                ("No date".to_string(), 5, 1),
+            ]
+        );
+
+        let (titles, groups) = grouped(&db, GroupBy::Priority);
+        assert_eq!(titles, ["later", "subtask", "undated", "overdue", "today", "friday"]);
+        assert_eq!(
+            groups,
+            [
+                ("High".to_string(), 0, 2),
+                ("Medium".to_string(), 2, 1),
+                ("Low".to_string(), 3, 1),
+                ("No priority".to_string(), 4, 2),
+            ]
+        );
+
+        let (titles, groups) = grouped(&db, GroupBy::None);
+        assert_eq!(titles, ["later", "subtask", "overdue", "undated", "today", "friday"]);
+        assert!(groups.is_empty());
     }
 }
@@ -1,17 +1,28 @@
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::vec;
 
+use crate::due_date::{self, parse_due_date};
+use crate::paths;
+use crate::recurrence::Recurrence;
+use crate::task::{Task, TaskLabel};
+use crate::task_view::Group;
 use crate::venom::{self, Venom, VenomFocus};
 
 use crate::edit_task_popup::EditTaskFocus;
 
-use crate::task::Priority;
+use crate::config::Action;
+use chrono::Local;
 use ratatui::widgets::block::Title;
 use ratatui::widgets::Clear;
 //use datetime::DatePiece;
 use ratatui::{
     prelude::*,
     style::{Color, Style, Stylize},
-    widgets::{Block, BorderType, Borders, Padding, Paragraph, Row, Table},
+    widgets::{
+        Block, BorderType, Borders, Cell, Padding, Paragraph, Row, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, Table, TableState,
+    },
 };
 use strum::IntoEnumIterator;
 
@@ -24,8 +35,26 @@ pub fn render(app: &mut Venom, f: &mut Frame) {
     let main_table = main_table(app);
     let summary_block = summary_block(app);
 
-    f.render_widget(main_table, layout[0]);
+    // the table only knows about rows, which include the group headers
+    let mut table_state = app.table_state().clone();
+    let selected_row = app.task_view().has_tasks().then(|| {
+        let idx = app.selected_task_idx();
+        let groups = app.task_view().groups();
+        let headers = groups.iter().filter(|group| group.start <= idx).count();
+        // scrolling up to the first task of a group brings its header into sight too
+        let starts_group = groups.iter().any(|group| group.start == idx);
+        if starts_group && table_state.offset() >= idx + headers {
+            *table_state.offset_mut() = idx + headers - 1;
+        }
+        idx + headers
+    });
+    table_state.select(selected_row);
+
+    f.render_stateful_widget(main_table, layout[0], &mut table_state);
     f.render_widget(summary_block, layout[1]);
+    record_table_areas(app, layout[0], table_state.offset());
+    render_scrollbar(app, f, layout[0], table_state.offset());
+    *app.table_state_mut() = table_state;
 
     if let VenomFocus::EditTaskPopup(_) = app.focus() {
         render_edit_task_popup(app, f);
@@ -33,6 +62,405 @@ pub fn render(app: &mut Venom, f: &mut Frame) {
     if let VenomFocus::EditLabelsPopup(_) = app.focus() {
         render_edit_label_popup(app, f);
     }
+    if let VenomFocus::OpenDatabasePopup(_) = app.focus() {
+        render_open_database_popup(app, f);
+    }
+    if let VenomFocus::PromptPopup(_) = app.focus() {
+        render_prompt_popup(app, f);
+    }
+    if let VenomFocus::ViewPickerPopup(_) = app.focus() {
+        render_view_picker_popup(app, f);
+    }
+    if let VenomFocus::FinderPopup(_) = app.focus() {
+        render_finder_popup(app, f);
+    }
+    if let VenomFocus::ArchivePopup(_) = app.focus() {
+        render_archive_popup(app, f);
+    }
+}
+
+fn render_archive_popup(app: &mut Venom, frame: &mut Frame) {
+    let area = centered_rect(frame.size(), 60, 50);
+    if let VenomFocus::ArchivePopup(popup) = app.focus() {
+        let popup = popup.borrow();
+        let highlight_style = Style::default()
+            .fg(app.config().theme.highlight)
+            .bold()
+            .italic();
+        let inactive_style = Style::default().fg(app.config().theme.inactive);
+
+        let rows = popup
+            .items()
+            .iter()
+            .enumerate()
+            .map(|(idx, task)| {
+                let task = task.borrow();
+                let style = if idx == popup.selected_idx() {
+                    highlight_style
+                } else {
+                    Style::default()
+                };
+                let completed = task
+                    .completed()
+                    .map(|completed| completed.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default();
+                Row::new(vec![
+                    Cell::from(if idx == popup.selected_idx() { "*" } else { " " }),
+                    Cell::from(Span::styled(completed, inactive_style)),
+                    Cell::from(label_spans(task.labels())),
+                    Cell::from(Span::styled(task.title().to_string(), style)),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        let label_width = popup
+            .items()
+            .iter()
+            .map(|task| label_spans(task.borrow().labels()).width() as u16)
+            .max()
+            .unwrap_or_default();
+        let mut state = TableState::default().with_selected(Some(popup.selected_idx()));
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(1),
+                Constraint::Length(10),
+                Constraint::Length(label_width),
+                Constraint::Percentage(100),
+            ],
+        )
+        .block(
+            Block::default()
+                .title(format!(" Archive ({}) ", popup.items().len()))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(
+                    Title::from(" Ent/r: Restore | d: Delete | Esc: Close ")
+                        .position(ratatui::widgets::block::Position::Bottom),
+                ),
+        );
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+}
+
+fn render_finder_popup(app: &mut Venom, frame: &mut Frame) {
+    let area = centered_rect(frame.size(), 60, 50);
+    if let VenomFocus::FinderPopup(popup) = app.focus() {
+        let popup = popup.borrow();
+        let results = popup.results();
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)])
+            .split(area);
+
+        let input = Paragraph::new(Line::from(vec![
+            Span::raw(popup.text().to_string()),
+            Span::styled(" ", Style::default().bg(app.config().theme.cursor)),
+        ]))
+        .block(
+            Block::default()
+                .title(" Find ")
+                .title(
+                    Title::from(format!(" {} found ", results.items().len()))
+                        .alignment(Alignment::Right),
+                )
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded),
+        );
+
+        let match_style = Style::default().fg(app.config().theme.highlight).bold();
+        let inactive_style = Style::default().fg(app.config().theme.inactive);
+        let rows = results
+            .items()
+            .iter()
+            .enumerate()
+            .map(|(idx, result)| {
+                let task = result.task.borrow();
+                let positions = result
+                    .title
+                    .as_ref()
+                    .map(|found| found.positions.as_slice())
+                    .unwrap_or_default();
+                let title =
+                    highlight_matches(task.title(), positions, Style::default(), match_style);
+                let notes = match &result.notes {
+                    Some((line, found)) => {
+                        highlight_matches(line, &found.positions, inactive_style, match_style)
+                    }
+                    None => Line::default(),
+                };
+                let shown = app
+                    .task_view()
+                    .tasks()
+                    .iter()
+                    .any(|shown| Rc::ptr_eq(shown, &result.task));
+                let mut row = Row::new(vec![
+                    Cell::from(if idx == results.selected_idx() { "*" } else { " " }),
+                    Cell::from(if task.is_done() { "[x]" } else { "[ ]" }),
+                    Cell::from(Span::styled(if shown { "" } else { "hidden" }, inactive_style)),
+                    Cell::from(title),
+                    Cell::from(notes),
+                ]);
+                let selection = app.config().theme.selection;
+                if let (true, Some(color)) = (idx == results.selected_idx(), selection) {
+                    row = row.style(Style::default().bg(color));
+                }
+                row
+            })
+            .collect::<Vec<_>>();
+
+        let mut state = TableState::default().with_selected(Some(results.selected_idx()));
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(1),
+                Constraint::Length(3),
+                Constraint::Length(6),
+                Constraint::Percentage(50),
+                Constraint::Percentage(50),
+            ],
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(
+                    Title::from(" Ent: Go To | Up/Down: Select | Esc: Cancel ")
+                        .position(ratatui::widgets::block::Position::Bottom),
+                ),
+        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(input, layout[0]);
+        frame.render_stateful_widget(table, layout[1], &mut state);
+    }
+}
+
+/// Text with the characters at the given positions picked out
+fn highlight_matches<'a>(
+    text: &str,
+    positions: &[usize],
+    base: Style,
+    matched: Style,
+) -> Line<'a> {
+    Line::from(
+        text.chars()
+            .enumerate()
+            .map(|(idx, c)| match positions.contains(&idx) {
+                true => Span::styled(c.to_string(), matched),
+                false => Span::styled(c.to_string(), base),
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// A scrollbar on the right border of the main table, when there are more rows than fit
+fn render_scrollbar(app: &Venom, f: &mut Frame, area: Rect, offset: usize) {
+    let rows = app.task_view().num_tasks() + app.task_view().groups().len();
+    let visible = usize::from(app.click_areas().table.height);
+    if rows <= visible {
+        return;
+    }
+    // the thumb reaches the bottom once the last row is in sight
+    let mut state = ScrollbarState::new(rows)
+        .viewport_content_length(visible)
+        .position(offset * rows / (rows - visible));
+    let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+        .begin_symbol(None)
+        .end_symbol(None)
+        .style(Style::default().fg(app.config().theme.border));
+    f.render_stateful_widget(
+        scrollbar,
+        area.inner(&Margin {
+            vertical: 1,
+            horizontal: 0,
+        }),
+        &mut state,
+    );
+}
+
+/// Note where the rows and checkboxes of the main table were drawn, for the mouse. `offset` is
+/// how many rows are scrolled past.
+fn record_table_areas(app: &mut Venom, area: Rect, offset: usize) {
+    let inner = Block::default()
+        .borders(Borders::ALL)
+        .padding(Padding::new(1, 1, 1, 1))
+        .inner(area);
+    // the column names take up the first line
+    let table = Rect {
+        y: inner.y + 1,
+        height: inner.height.saturating_sub(1),
+        ..inner
+    };
+
+    let mut table_rows = vec![];
+    let groups = app.task_view().groups();
+    for idx in 0..app.task_view().num_tasks() {
+        if groups.iter().any(|group| group.start == idx) {
+            table_rows.push(None);
+        }
+        table_rows.push(Some(idx));
+    }
+
+    let click_areas = app.click_areas_mut();
+    click_areas.table = table;
+    click_areas.table_rows = table_rows.split_off(offset.min(table_rows.len()));
+    // after the selection marker and the space between columns
+    click_areas.done_column = table.x + 2..table.x + 5;
+    click_areas.fields = None;
+}
+
+fn render_view_picker_popup(app: &mut Venom, frame: &mut Frame) {
+    let area = centered_rect(frame.size(), 60, 50);
+    if let VenomFocus::ViewPickerPopup(popup) = app.focus() {
+        let popup = popup.borrow();
+        let highlight_style = Style::default()
+            .fg(app.config().theme.highlight)
+            .bold()
+            .italic();
+        let inactive_style = Style::default().fg(app.config().theme.inactive);
+
+        let rows = popup
+            .items()
+            .iter()
+            .enumerate()
+            .map(|(idx, view)| {
+                let current = if Some(view.name.as_str()) == app.task_view().name() {
+                    "*"
+                } else {
+                    " "
+                };
+                let style = if idx == popup.selected_idx() {
+                    highlight_style
+                } else {
+                    Style::default()
+                };
+                let source = if app.is_config_view(&view.name) {
+                    "config"
+                } else {
+                    "saved"
+                };
+                let mut description = vec![];
+                if !view.query.is_empty() {
+                    description.push(format!("/{}", view.query));
+                }
+                if !view.labels.is_empty() {
+                    description.push(format!("{} {}", view.label_match, view.labels.join(" ")));
+                }
+                Row::new(vec![
+                    Span::raw(current),
+                    Span::raw(format!("{}", idx + 1)),
+                    Span::styled(view.name.clone(), style),
+                    Span::styled(source, inactive_style),
+                    Span::raw(description.join("  ")),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        let name_width = popup
+            .items()
+            .iter()
+            .map(|view| view.name.chars().count() as u16)
+            .max()
+            .unwrap_or_default();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(name_width),
+                Constraint::Length(6),
+                Constraint::Percentage(100),
+            ],
+        )
+        .block(
+            Block::default()
+                .title(" Views ")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(
+                    Title::from(
+                        " Ent: Switch | n: Save Current | d: Delete | 0: All Tasks | Esc: Cancel ",
+                    )
+                    .position(ratatui::widgets::block::Position::Bottom),
+                ),
+        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(table, area);
+    }
+}
+
+fn render_open_database_popup(app: &mut Venom, frame: &mut Frame) {
+    let area = centered_rect(frame.size(), 60, 50);
+    if let VenomFocus::OpenDatabasePopup(popup) = app.focus() {
+        let popup = popup.borrow();
+        let highlight_style = Style::default()
+            .fg(app.config().theme.highlight)
+            .bold()
+            .italic();
+
+        let rows = popup
+            .items()
+            .iter()
+            .enumerate()
+            .map(|(idx, path)| {
+                let current = if path == app.save_path() { "*" } else { " " };
+                let style = if idx == popup.selected_idx() {
+                    highlight_style
+                } else {
+                    Style::default()
+                };
+                Row::new(vec![
+                    Span::raw(current),
+                    Span::styled(path.display().to_string(), style),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        let table = Table::new(rows, [Constraint::Length(1), Constraint::Percentage(100)]).block(
+            Block::default()
+                .title(" Open Database ")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(
+                    Title::from(" Ent: Open | n: New | Esc: Cancel ")
+                        .position(ratatui::widgets::block::Position::Bottom),
+                ),
+        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(table, area);
+    }
+}
+
+fn render_prompt_popup(app: &mut Venom, frame: &mut Frame) {
+    let area = centered_rect(frame.size(), 60, 50);
+    let area = Rect {
+        height: std::cmp::min(area.height, 3),
+        ..area
+    };
+    if let VenomFocus::PromptPopup(popup) = app.focus() {
+        let popup = popup.borrow();
+        let mut block = Block::default()
+            .title(format!(" {} ", popup.kind().title()))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded);
+        if let Some(error) = popup.error() {
+            block = block.title(
+                Title::from(Span::styled(
+                    format!(" {} ", error),
+                    Style::default().fg(app.config().theme.error).bold(),
+                ))
+                .position(ratatui::widgets::block::Position::Bottom),
+            );
+        }
+        let input = Paragraph::new(Line::from(vec![
+            Span::raw(popup.text().to_string()),
+            Span::styled(" ", Style::default().bg(app.config().theme.cursor)),
+        ]))
+        .block(block);
+        frame.render_widget(Clear, area);
+        frame.render_widget(input, area);
+    }
 }
 fn render_edit_label_popup(app: &mut Venom, frame: &mut Frame) {
     let area = centered_rect(frame.size(), 60, 50);
@@ -46,7 +474,9 @@ fn render_edit_label_popup(app: &mut Venom, frame: &mut Frame) {
             .block(edit_block)
             .base(Style::default())
             .cursor_style(match popup.borrow().text_editor().mode {
-                edtui::EditorMode::Insert => Style::default().bg(Color::LightCyan).fg(Color::Black),
+                edtui::EditorMode::Insert => Style::default()
+                    .bg(app.config().theme.cursor)
+                    .fg(Color::Black),
                 _ => Style::default().fg(Color::Black).bg(Color::White),
             })
             .hide_status_line();
@@ -60,11 +490,12 @@ fn render_edit_label_popup(app: &mut Venom, frame: &mut Frame) {
 
 fn render_edit_task_popup(app: &mut Venom, frame: &mut Frame) {
     let area = centered_rect(frame.size(), 60, 50);
+    let mut fields_area = None;
 
     if let VenomFocus::EditTaskPopup(popup) = app.focus() {
         let active_color = Color::default();
-        let inactive_color = Color::DarkGray;
-        let highlight_color = Color::Blue;
+        let inactive_color = app.config().theme.inactive;
+        let highlight_color = app.config().theme.highlight;
         let inactive_style = Style::default().fg(inactive_color);
         let active_style = Style::default().fg(active_color);
         let highlight_style = Style::default().fg(highlight_color).bold().italic();
@@ -80,6 +511,7 @@ fn render_edit_task_popup(app: &mut Venom, frame: &mut Frame) {
             .direction(Direction::Horizontal)
             .constraints([Constraint::Percentage(20), Constraint::Percentage(80)])
             .split(area);
+        fields_area = Some(Block::default().borders(Borders::ALL).inner(layout[0]));
 
         let mut field_block = Block::default()
             .title(" Field ")
@@ -105,11 +537,51 @@ fn render_edit_task_popup(app: &mut Venom, frame: &mut Frame) {
             .block(edit_block)
             .base(Style::default())
             .cursor_style(match pop_borrow.text_editor().mode {
-                edtui::EditorMode::Insert => Style::default().bg(Color::LightCyan).fg(Color::Black),
+                edtui::EditorMode::Insert => Style::default()
+                    .bg(app.config().theme.cursor)
+                    .fg(Color::Black),
                 _ => Style::default().fg(Color::Black).bg(Color::White),
             })
             .hide_status_line();
 
+        // due dates and repeat rules can be written many ways, so show what the text turns into
+        // while typing
+        let error_style = Style::default().fg(app.config().theme.error);
+        let preview = match property {
+            venom::EditableTaskProperty::DueDate => {
+                Some(match parse_due_date(&pop_borrow.text(), Local::now()) {
+                    Ok(Some(date)) => Line::raw(date.format("%a %e %b %Y %H:%M").to_string()),
+                    Ok(None) => Line::raw("No due date"),
+                    Err(err) => Line::styled(err.to_string(), error_style),
+                })
+            }
+            venom::EditableTaskProperty::Repeat => {
+                let due_date = pop_borrow.task().borrow().due_date();
+                Some(match Recurrence::parse(&pop_borrow.text(), due_date) {
+                    Ok(Some(recurrence)) => {
+                        let next = recurrence.next_due(due_date, Local::now());
+                        Line::raw(format!(
+                            "Repeats {recurrence}, next due {}",
+                            next.format("%a %e %b %Y %H:%M")
+                        ))
+                    }
+                    Ok(None) => Line::raw("Does not repeat"),
+                    Err(err) => Line::styled(err.to_string(), error_style),
+                })
+            }
+            _ => pop_borrow
+                .error()
+                .map(|error| Line::styled(error.to_string(), error_style)),
+        };
+
+        let edit_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(match preview {
+                Some(_) => [Constraint::Min(3), Constraint::Length(3)],
+                None => [Constraint::Min(3), Constraint::Length(0)],
+            })
+            .split(layout[1]);
+
         let edit_paragraph = pop_borrow.text_editor_widget().theme(editor_theme);
 
         let mut field_rows = vec![];
@@ -127,19 +599,31 @@ fn render_edit_task_popup(app: &mut Venom, frame: &mut Frame) {
 
         frame.render_widget(Clear, area);
         frame.render_widget(field_table, layout[0]);
-        frame.render_widget(edit_paragraph, layout[1]);
+        frame.render_widget(edit_paragraph, edit_layout[0]);
+        if let Some(preview) = preview {
+            let preview = Paragraph::new(preview).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .set_style(edit_style),
+            );
+            frame.render_widget(preview, edit_layout[1]);
+        }
     }
+    app.click_areas_mut().fields = fields_area;
 }
 
-fn summary_block(app: &Venom) -> Paragraph {
+fn summary_block(app: &Venom) -> Paragraph<'_> {
     if app.task_view().has_no_tasks() {
         return Paragraph::default();
     }
     let active_task = app.selected_task();
     let prio = active_task.borrow().priority();
-    let (color, word) = prio.formatting();
+    let (_, word) = prio.formatting();
+    let color = app.config().theme.priority(prio);
 
     let mut summary_text = vec![
+        Line::raw(format!("Id      : {}", active_task.borrow().id())),
         Line::raw(format!("Title   : {}", active_task.borrow().title())),
         Line::raw(format!(
             "Due Date: {} {}",
@@ -153,28 +637,63 @@ fn summary_block(app: &Venom) -> Paragraph {
     ];
 
     let active_task_borrow = active_task.borrow();
-    let label = active_task_borrow.label();
-    let (label_string, label_color) = if let Some(label) = label {
-        (
+    let mut label_line = vec![Span::raw("Labels  : ")];
+    for (idx, label) in active_task_borrow.labels().iter().enumerate() {
+        if idx > 0 {
+            label_line.push(Span::raw(", "));
+        }
+        let label = label.borrow();
+        label_line.push(Span::styled(
             format!(
                 "{} ({})",
-                label.borrow().long_name(),
-                label.borrow().short_name_string()
+                label.long_name(),
+                label.short_name_string().trim_end()
             ),
-            label.borrow().color(),
-        )
-    } else {
-        (String::new(), Color::default())
-    };
+            Style::default().fg(label.color()),
+        ));
+    }
+    summary_text.push(Line::from(label_line));
+
+    if let Some(recurrence) = active_task_borrow.recurrence() {
+        summary_text.push(Line::raw(format!("Repeats : {recurrence}")));
+        let done = match active_task_borrow.completions().last() {
+            Some(last) => format!(
+                "{} times, last {}",
+                active_task_borrow.completions().len(),
+                last.format("%Y-%m-%d %H:%M")
+            ),
+            None => "never".to_string(),
+        };
+        summary_text.push(Line::raw(format!("Done    : {done}")));
+    }
+
+    let (done, total) = app.task_db().progress(active_task_borrow.id());
+    if total > 0 {
+        summary_text.push(Line::raw(format!("Subtasks: {done}/{total} done")));
+    }
+    if let Some(parent) = active_task_borrow
+        .parent()
+        .and_then(|parent| app.task_db().task_by_id(parent))
+    {
+        summary_text.push(Line::raw(format!("Parent  : {}", parent.borrow().title())));
+    }
 
-    //let label = label.clone();
-    summary_text.push(Line::from(vec![
-        Span::raw("Label   : "),
-        match label {
-            None => Span::default(),
-            Some(_) => Span::styled(label_string, Style::default().fg(label_color)),
-        },
-    ]));
+    let timestamps = [
+        ("Created ", active_task_borrow.created()),
+        ("Modified", active_task_borrow.modified()),
+        (
+            "Finished",
+            active_task_borrow
+                .completed()
+                .filter(|_| active_task_borrow.is_done()),
+        ),
+    ];
+    for (name, time) in timestamps {
+        if let Some(time) = time {
+            let time = time.format("%Y-%m-%d %H:%M");
+            summary_text.push(Line::raw(format!("{name}: {time}")));
+        }
+    }
 
     summary_text.push(Line::default());
     summary_text.push(Line::raw("Notes   :"));
@@ -193,11 +712,110 @@ fn summary_block(app: &Venom) -> Paragraph {
                 .title(" Summary ")
                 .padding(Padding::new(1, 1, 1, 1))
                 .borders(Borders::ALL)
-                .border_type(BorderType::Rounded),
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(app.config().theme.border)),
         )
 }
 
-fn main_table(app: &Venom) -> Table {
+/// The title of the task shown at `idx` of the view, indented under its parent task. Tasks with
+/// subtasks show whether they are folded and how many subtasks are done.
+fn title_cell(app: &Venom, idx: usize, task: &Task) -> String {
+    let mut cell = "  ".repeat(app.task_view().depth(idx));
+    let (done, total) = app.task_db().progress(task.id());
+    if total > 0 {
+        cell.push_str(if task.is_collapsed() { "▸ " } else { "▾ " });
+    }
+    cell.push_str(task.title());
+    if task.recurrence().is_some() {
+        cell.push_str(" ↻");
+    }
+    if total > 0 {
+        cell.push_str(&format!(" ({done}/{total})"));
+    }
+    cell
+}
+
+/// The query the main view is filtered with, if any
+fn query_title(app: &Venom) -> Title<'_> {
+    let text = match app.task_view().query() {
+        Some(query) => format!(" /{query} "),
+        None => String::new(),
+    };
+    Title::from(Span::styled(
+        text,
+        Style::default().fg(app.config().theme.highlight),
+    ))
+    .alignment(Alignment::Right)
+}
+
+/// Which task is selected out of how many, like ` 3 of 12 `
+fn position_title(app: &Venom) -> Title<'_> {
+    let mut text = match app.task_view().has_tasks() {
+        true => format!(
+            " {} of {} ",
+            app.selected_task_idx() + 1,
+            app.task_view().num_tasks()
+        ),
+        false => String::new(),
+    };
+    if app.has_marks() {
+        let mode = if app.is_visual_mode() { "VISUAL " } else { "" };
+        text = format!(" {mode}{} marked |{text}", app.marked_ids().len());
+    }
+    Title::from(text).alignment(Alignment::Right)
+}
+
+/// Which labels the main view is filtered on, if any
+fn label_filter_title(app: &Venom) -> Title<'_> {
+    let filter = app.task_view().label_filter();
+    let mut spans = vec![];
+    if !filter.is_empty() {
+        let how = match filter.len() {
+            1 => "label".to_string(),
+            _ => format!("{} labels", app.task_view().label_match()),
+        };
+        spans.push(Span::raw(format!(" {how}: ")));
+        spans.extend(label_spans(filter).spans);
+        spans.push(Span::raw(" "));
+    }
+    Title::from(Line::from(spans)).alignment(Alignment::Right)
+}
+
+/// The short names of labels, each in its own color
+fn label_spans(labels: &[Rc<RefCell<TaskLabel>>]) -> Line<'static> {
+    let mut spans = vec![];
+    for (idx, label) in labels.iter().enumerate() {
+        if idx > 0 {
+            spans.push(Span::raw(" "));
+        }
+        let label = label.borrow();
+        spans.push(Span::styled(
+            label.short_name_string().trim_end().to_string(),
+            Style::default().fg(label.color()),
+        ));
+    }
+    Line::from(spans)
+}
+
+/// Text of the row which starts a group, with the number of tasks in it
+fn group_header(group: &Group) -> String {
+    format!("{} ({})", group.title, group.len)
+}
+
+/// A row which can not be selected, heading the tasks of a group
+fn group_header_row<'a>(app: &Venom, group: &Group) -> Row<'a> {
+    let style = Style::default().fg(app.config().theme.highlight).bold();
+    Row::new(vec![
+        Cell::default(),
+        Cell::default(),
+        Cell::default(),
+        Cell::from(Span::styled(group_header(group), style)),
+        Cell::default(),
+        Cell::default(),
+    ])
+}
+
+fn main_table(app: &Venom) -> Table<'_> {
     let header_style = Style::default().fg(Color::default()).underlined();
 
     let due_date_col_name = "Due Date".to_string();
@@ -215,20 +833,30 @@ fn main_table(app: &Venom) -> Table {
     let done_col_name = "".to_string();
     let done_col_name = Span::styled(done_col_name, Style::default());
 
-    let header_row = Row::new(vec![
+    let relative_col_name = "Due In".to_string();
+    let relative_col_name = Span::styled(relative_col_name, header_style);
+
+    let show_relative = app.config().due.relative_column;
+    let mut header_row = vec![
         Span::default(),
         done_col_name,
         label_col_name.clone(),
         title_col_name,
         due_date_col_name.clone(),
         due_time_col_name.clone(),
-    ]);
+    ];
+    if show_relative {
+        header_row.push(relative_col_name.clone());
+    }
+    let header_row = Row::new(header_row);
 
 
     let mut rows = vec![];
 
     let mut date_constraint = due_date_col_name.width() as u16;
     let mut time_constraint = due_time_col_name.width() as u16;
+    let mut relative_constraint = relative_col_name.width() as u16;
+    let marked = app.marked_ids();
     app.task_view()
         .tasks()
         .iter()
@@ -237,35 +865,28 @@ fn main_table(app: &Venom) -> Table {
         .map(|(idx, task)| {
             let active_task = idx == app.selected_task_idx();
 
-            let priority_color = match task.borrow().priority() {
-                Priority::None => Color::default(),
-                Priority::Low => Color::Green,
-                Priority::Medium => Color::Yellow,
-                Priority::High => Color::Red,
-            };
+            let priority_color = app.config().theme.priority(task.borrow().priority());
 
+            // the title takes the color of the first label
             let mut label_style = Style::default();
-            if task.borrow().label().is_some() {
-                label_style =
-                    label_style.fg(task.borrow().label().as_ref().unwrap().borrow().color());
+            if let Some(label) = task.borrow().labels().first() {
+                label_style = label_style.fg(label.borrow().color());
             }
-            let label_col = match task.borrow().label().as_ref() {
-                None => "".to_string(),
-                Some(label) => label.borrow().short_name().iter().collect::<String>(),
-            };
-            let label_col = Span::styled(label_col, label_style);
+            let label_col = label_spans(task.borrow().labels());
 
             let priority_style = Style::default().fg(priority_color);
             let borrow = task.borrow();
-            let content_col = borrow.title().to_string();
+            let content_col = title_cell(app, idx, &borrow);
             let content_col = Span::styled(content_col, label_style);
 
+            let is_marked = marked.contains(&task.borrow().id());
             let selected_col = if active_task {
-                String::from("*")
+                Span::styled("*", priority_style)
+            } else if is_marked {
+                Span::styled("+", Style::default().fg(app.config().theme.highlight).bold())
             } else {
-                String::from(" ")
+                Span::raw(" ")
             };
-            let selected_col = Span::styled(selected_col, priority_style);
 
             let done_col = if task.borrow().is_done() {
                 String::from("[x]")
@@ -276,58 +897,121 @@ fn main_table(app: &Venom) -> Table {
 
             let due_date_col = task.borrow().date_string();
             let due_time_col = task.borrow().time_string();
+            // like the urgency colours, a finished task is no longer due
+            let relative_col = match task.borrow().due_date() {
+                Some(due) if !task.borrow().is_done() => due_date::relative_string(due, app.now()),
+                _ => String::new(),
+            };
             date_constraint = std::cmp::max(date_constraint, due_date_col.len() as u16);
             time_constraint = std::cmp::max(time_constraint, due_time_col.len() as u16);
-            //let due_date_col = Span::styled(due_date_col, priority_style);
-            //let due_time_col = Span::styled(due_time_col, priority_style);
-            let due_date_col = Span::raw(due_date_col);
-            let due_time_col = Span::raw(due_time_col);
-
-            let row = Row::new(vec![
-                selected_col,
-                done_col,
-                label_col,
-                content_col,
-                due_date_col,
-                due_time_col,
-            ]);
-
-            row
+            relative_constraint = std::cmp::max(relative_constraint, relative_col.len() as u16);
+            let urgency = task
+                .borrow()
+                .urgency(app.now(), app.config().due.soon_days);
+            let due_style = app.config().theme.urgency(urgency);
+            let due_date_col = Span::styled(due_date_col, due_style);
+            let due_time_col = Span::styled(due_time_col, due_style);
+            let relative_col = Span::styled(relative_col, due_style);
+
+            let mut cells = vec![
+                Cell::from(selected_col),
+                Cell::from(done_col),
+                Cell::from(label_col),
+                Cell::from(content_col),
+                Cell::from(due_date_col),
+                Cell::from(due_time_col),
+            ];
+            if show_relative {
+                cells.push(Cell::from(relative_col));
+            }
+            let mut row = Row::new(cells);
+            if let (true, Some(color)) = (active_task, app.config().theme.selection) {
+                row = row.style(Style::default().bg(color));
+            }
+
+            (idx, row)
         })
-        .for_each(|row| rows.push(row));
+        .for_each(|(idx, row)| {
+            let groups = app.task_view().groups();
+            if let Some(group) = groups.iter().find(|group| group.start == idx) {
+                rows.push(group_header_row(app, group));
+            }
+            rows.push(row);
+        });
 
     let title_constraint = app
         .task_view()
         .tasks()
         .iter()
-        .map(|task| task.borrow().title().len() as u16)
+        .enumerate()
+        .map(|(idx, task)| title_cell(app, idx, &task.borrow()).chars().count() as u16)
+        .chain(
+            app.task_view()
+                .groups()
+                .iter()
+                .map(|group| group_header(group).chars().count() as u16),
+        )
         .max()
         .unwrap_or(15)
         + 1;
 
-    let main_table = Table::new(
-        rows,
-        Constraint::from_lengths([
-            1,
-            3,
-            5,
-            std::cmp::max(title_constraint + 1, 6),
-            date_constraint + 1,
-            time_constraint + 1,
-        ]),
-    )
-    .header(header_row)
-    .block(
-        Block::default()
-            .title(" Tasks ")
-            .padding(Padding::new(1, 1, 1, 1))
-            .borders(Borders::ALL)
-            .border_type(BorderType::Rounded)
-            .title(
-                Title::from(" Esc: Quit | a: Add | d: delete |  Ent: Edit Task |  l: Edit Labels ")
+    let label_constraint = app
+        .task_view()
+        .tasks()
+        .iter()
+        .map(|task| task.borrow().labels_string().chars().count() as u16)
+        .max()
+        .unwrap_or_default();
+
+    let title = match app.task_view().name() {
+        Some(view) => format!(" Tasks: {} | {view} ", paths::display_name(app.save_path())),
+        None => format!(" Tasks: {} ", paths::display_name(app.save_path())),
+    };
+    let mut block = Block::default()
+        .title(title)
+        .title(query_title(app))
+        .title(label_filter_title(app))
+        .title(position_title(app))
+        .padding(Padding::new(1, 1, 1, 1))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(app.config().theme.border));
+    block = match app.error_message() {
+        Some(message) => block.title(
+            Title::from(Span::styled(
+                format!(" {} ", message),
+                Style::default().fg(app.config().theme.error).bold(),
+            ))
+            .position(ratatui::widgets::block::Position::Bottom),
+        ),
+        None => {
+            let hints = Action::HINTS
+                .iter()
+                .map(|&action| app.config().keys.hint(action))
+                .filter(|hint| !hint.is_empty())
+                .collect::<Vec<_>>()
+                .join(" | ");
+            block.title(
+                Title::from(format!(" {} ", hints))
                     .position(ratatui::widgets::block::Position::Bottom),
-            ),
-    );
+            )
+        }
+    };
+
+    let mut widths = vec![
+        1,
+        3,
+        std::cmp::max(label_constraint, 5),
+        std::cmp::max(title_constraint + 1, 6),
+        date_constraint + 1,
+        time_constraint + 1,
+    ];
+    if show_relative {
+        widths.push(relative_constraint + 1);
+    }
+    let main_table = Table::new(rows, Constraint::from_lengths(widths))
+    .header(header_row)
+    .block(block);
     main_table
 }
 
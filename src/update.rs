@@ -1,53 +1,98 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{
+    KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 use edtui::EditorMode;
 use edtui::Input;
 use std::cell::RefCell;
 use std::rc::Rc;
 //use serde_json::json;
 
+use crate::config::Action;
 use crate::edit_labels_popup::EditLabelsPopup;
 use crate::edit_task_popup::EditTaskFocus;
 use crate::edit_task_popup::EditTaskPopup;
-use crate::task::TaskLabel;
+use crate::paths;
+use crate::prompt_popup::{PromptKind, PromptPopup};
+use crate::query::Query;
+use crate::sort::Sort;
+use crate::task::{Priority, TaskLabel};
+use color_eyre::eyre::eyre;
 use crate::venom::Venom;
 use crate::venom::VenomFocus;
+use crate::venom::EditableTaskProperty;
+use strum::IntoEnumIterator;
 
 type KC = KeyCode;
 type KM = KeyModifiers;
 
 pub fn update(app: &mut Venom, ke: KeyEvent) {
+    app.clear_error();
     let focus = app.focus().clone();
     match focus {
         VenomFocus::MainView => {
-            match (ke.code, ke.modifiers) {
-                (KC::Esc, _) | (KC::Char('c'), KM::CONTROL) => app.quit(),
-                (KC::Down | KC::Char('j'), _) => app.increment_task_idx(),
-                (KC::Up | KC::Char('k'), _) => app.decrement_task_idx(),
-                (KC::Char('a'), _) => app.add_task(),
-                (KC::Enter, _) => {
-                    if app.selected_task_idx() < app.task_view().tasks().len() {
-                        app.edit_current_task();
-                    }
+            let Some(action) = app.config().keys.action(ke) else {
+                // number keys which are not bound to anything switch views, 0 being every task
+                match ke.code {
+                    KC::Char('0') => app.default_view(),
+                    KC::Char(c @ '1'..='9') => app.switch_view(c as usize - '1' as usize),
+                    _ => {}
                 }
-                (KC::Char('l'), _) => app.edit_labels(),
-                (KC::Char('d'), _) => {
+                return;
+            };
+            let has_tasks = app.task_view().has_tasks();
+            match action {
+                Action::Quit if app.has_marks() => app.clear_marks(),
+                Action::Quit => app.quit(),
+                Action::Down => app.increment_task_idx(),
+                Action::Up => app.decrement_task_idx(),
+                Action::PageDown => app.page_down(),
+                Action::PageUp => app.page_up(),
+                Action::First => app.select_first_task(),
+                Action::Last => app.select_last_task(),
+                Action::Add => app.add_task(),
+                Action::AddSubtask if has_tasks => app.add_subtask(),
+                Action::Edit if has_tasks => app.edit_current_task(),
+                Action::EditLabels => app.edit_labels(),
+                Action::OpenDatabase => app.pick_database(),
+                Action::Delete if app.has_marks() => app.remove_marked_tasks(),
+                Action::Delete if has_tasks => {
                     app.remove_selected_task();
-                    app.update_view();
                     app.save_file();
                 }
-                (KC::Char('r'), _) => app.add_task_based_on_current(),
-                (KC::Char(' '), _) => {
+                Action::Undo => app.undo(),
+                Action::Redo => app.redo(),
+                Action::Repeat if has_tasks => app.add_task_based_on_current(),
+                Action::ToggleDone if app.has_marks() => app.toggle_target_tasks_done(),
+                Action::ToggleDone if has_tasks => {
                     app.toggle_selected_task();
                     app.save_file();
                     app.update_view();
                 }
-                (KC::Tab, _) => {
-                    app.toggle_completed_task_view();
-                }
-                (KC::Char('f'),    _ ) => {
-                    app.toggle_selected_label();
-
+                Action::CycleCompletedView => app.toggle_completed_task_view(),
+                Action::CycleLabelFilter => app.toggle_selected_label(),
+                Action::FilterLabels => app.filter_labels(),
+                Action::ToggleLabelMatch => app.toggle_label_match(),
+                Action::Query => app.query(),
+                Action::Find => app.find(),
+                Action::PickView => app.pick_view(),
+                Action::Sort => app.prompt_sort(),
+                Action::MoveDown if has_tasks => app.move_selected_task(false),
+                Action::MoveUp if has_tasks => app.move_selected_task(true),
+                Action::Indent if has_tasks => app.indent_selected_task(),
+                Action::Outdent if has_tasks => app.outdent_selected_task(),
+                Action::ToggleCollapsed if has_tasks => app.toggle_selected_collapsed(),
+                Action::CycleGroup => app.toggle_group_by(),
+                Action::ToggleMark if has_tasks => app.toggle_mark(),
+                Action::VisualMode if has_tasks => app.toggle_visual_mode(),
+                Action::SetPriority if has_tasks => app.prompt(PromptKind::Priority),
+                Action::SetLabels if has_tasks => app.prompt(PromptKind::Labels),
+                Action::SetDueDate if has_tasks => app.prompt(PromptKind::DueDate),
+                Action::Archive if has_tasks => {
+                    if let Err(err) = app.archive_target_tasks() {
+                        app.report_error(&err);
+                    }
                 }
+                Action::ShowArchive => app.browse_archive(0),
                 _ => {}
             };
         }
@@ -96,6 +141,117 @@ pub fn update(app: &mut Venom, ke: KeyEvent) {
                 }
             }
         }
+        VenomFocus::OpenDatabasePopup(popup) => match (ke.code, ke.modifiers) {
+            (KC::Esc, _) | (KC::Char('c'), KM::CONTROL) => app.set_focus(VenomFocus::MainView),
+            (KC::Down | KC::Char('j'), _) => {
+                popup.borrow_mut().increment_selected();
+            }
+            (KC::Up | KC::Char('k'), _) => {
+                popup.borrow_mut().decrement_selected();
+            }
+            (KC::Char('n'), _) => app.prompt(PromptKind::NewDatabase),
+            (KC::Enter, _) => {
+                app.set_focus(VenomFocus::MainView);
+                let selected = popup.borrow().selected().cloned();
+                if let Some(path) = selected {
+                    app.open_database(path);
+                }
+            }
+            _ => {}
+        },
+        VenomFocus::ViewPickerPopup(popup) => match (ke.code, ke.modifiers) {
+            (KC::Esc, _) | (KC::Char('c'), KM::CONTROL) => app.set_focus(VenomFocus::MainView),
+            (KC::Down | KC::Char('j'), _) => {
+                popup.borrow_mut().increment_selected();
+            }
+            (KC::Up | KC::Char('k'), _) => {
+                popup.borrow_mut().decrement_selected();
+            }
+            (KC::Char('n'), _) => app.prompt_save_view(),
+            (KC::Char('d'), _) => {
+                let selected = popup.borrow().selected().map(|view| view.name.clone());
+                if let Some(name) = selected {
+                    match app.delete_view(&name) {
+                        Ok(()) => app.pick_view(),
+                        Err(err) => app.report_error(&err),
+                    }
+                }
+            }
+            (KC::Char('0'), _) => {
+                app.set_focus(VenomFocus::MainView);
+                app.default_view();
+            }
+            (KC::Enter, _) => {
+                app.set_focus(VenomFocus::MainView);
+                let selected = popup.borrow().selected_idx();
+                if selected < popup.borrow().items().len() {
+                    app.switch_view(selected);
+                }
+            }
+            _ => {}
+        },
+        VenomFocus::FinderPopup(popup) => match (ke.code, ke.modifiers) {
+            (KC::Esc, _) | (KC::Char('c'), KM::CONTROL) => app.set_focus(VenomFocus::MainView),
+            (KC::Down, _) | (KC::Char('n' | 'j'), KM::CONTROL) => {
+                popup.borrow_mut().results_mut().increment_selected();
+            }
+            (KC::Up, _) | (KC::Char('p' | 'k'), KM::CONTROL) => {
+                popup.borrow_mut().results_mut().decrement_selected();
+            }
+            (KC::Enter, _) => {
+                let selected = popup
+                    .borrow()
+                    .results()
+                    .selected()
+                    .map(|result| Rc::clone(&result.task));
+                match selected {
+                    Some(task) => app.jump_to_task(&task),
+                    None => app.set_focus(VenomFocus::MainView),
+                }
+            }
+            (KC::Backspace, _) => {
+                popup.borrow_mut().pop();
+            }
+            (KC::Char(c), KM::NONE | KM::SHIFT) => {
+                popup.borrow_mut().push(c);
+            }
+            _ => {}
+        },
+        VenomFocus::ArchivePopup(popup) => match (ke.code, ke.modifiers) {
+            (KC::Esc, _) | (KC::Char('c'), KM::CONTROL) => app.set_focus(VenomFocus::MainView),
+            (KC::Down | KC::Char('j'), _) => {
+                popup.borrow_mut().increment_selected();
+            }
+            (KC::Up | KC::Char('k'), _) => {
+                popup.borrow_mut().decrement_selected();
+            }
+            (KC::Enter | KC::Char('r'), _) => {
+                let selected = popup.borrow().selected().cloned();
+                if let Some(task) = selected {
+                    app.restore_archived_task(&task);
+                    app.browse_archive(popup.borrow().selected_idx());
+                }
+            }
+            (KC::Char('d'), _) => {
+                let selected = popup.borrow().selected().cloned();
+                if let Some(task) = selected {
+                    app.remove_archived_task(&task);
+                    app.browse_archive(popup.borrow().selected_idx());
+                }
+            }
+            _ => {}
+        },
+        VenomFocus::PromptPopup(popup) => match (ke.code, ke.modifiers) {
+            (KC::Esc, _) | (KC::Char('c'), KM::CONTROL) => app.set_focus(VenomFocus::MainView),
+            (KC::Enter, _) => submit_prompt(app, &popup),
+            (KC::Backspace, _) => {
+                popup.borrow_mut().pop();
+            }
+            (KC::Char(c), KM::NONE | KM::SHIFT) => {
+                popup.borrow_mut().push(c);
+            }
+            _ => {}
+        },
         VenomFocus::EditLabelsPopup(popup) => match (ke.code, ke.modifiers) {
             (KC::Esc, _) | (KC::Char('c'), KM::CONTROL) => {
                 if popup.borrow().text_editor().mode != EditorMode::Normal {
@@ -112,10 +268,113 @@ pub fn update(app: &mut Venom, ke: KeyEvent) {
     }
 }
 
+/// Clicks and the scroll wheel in the main table and the fields of the edit task popup. Other
+/// popups are left to the keyboard.
+pub fn update_mouse(app: &mut Venom, me: MouseEvent) {
+    let focus = app.focus().clone();
+    match focus {
+        VenomFocus::MainView => match me.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                let Some(idx) = app.click_areas().task_at(me.column, me.row) else {
+                    return;
+                };
+                app.clear_error();
+                let task = Rc::clone(&app.task_view().tasks()[idx]);
+                app.select_task(&task);
+                if app.click_areas().done_column.contains(&me.column) {
+                    app.toggle_selected_task();
+                    app.save_file();
+                    app.update_view();
+                } else if app.click_areas_mut().click_task(idx) {
+                    app.edit_current_task();
+                }
+            }
+            // unlike the keys, the wheel stops at either end of the list
+            MouseEventKind::ScrollDown
+                if app.selected_task_idx() + 1 < app.task_view().num_tasks() =>
+            {
+                app.increment_task_idx()
+            }
+            MouseEventKind::ScrollUp if app.selected_task_idx() > 0 => app.decrement_task_idx(),
+            _ => {}
+        },
+        VenomFocus::EditTaskPopup(popup) => {
+            let MouseEventKind::Down(MouseButton::Left) = me.kind else {
+                return;
+            };
+            let Some(property) = app
+                .click_areas()
+                .field_at(me.column, me.row)
+                .and_then(|idx| EditableTaskProperty::iter().nth(idx))
+            else {
+                return;
+            };
+            // keep what was typed so far, as leaving the editor with escape would
+            if popup.borrow().focus() == EditTaskFocus::Edit {
+                escape_task_edit(app, &popup);
+                if popup.borrow().focus() == EditTaskFocus::Edit {
+                    return;
+                }
+            }
+            let text = popup.borrow().task().borrow().text_to_edit(property);
+            popup.borrow_mut().set_property(property).load_text(&text);
+        }
+        _ => {}
+    }
+}
+
+/// Act on the text of a prompt. The prompt stays open showing the error if the text is no good.
+fn submit_prompt(app: &mut Venom, popup: &RefCell<PromptPopup>) {
+    let kind = popup.borrow().kind();
+    let text = popup.borrow().text().to_string();
+    let result = match kind {
+        PromptKind::NewDatabase => paths::database_named(&text).map(|path| {
+            app.set_focus(VenomFocus::MainView);
+            app.open_database(path);
+        }),
+        PromptKind::LabelFilter => app.task_db().labels_by_tags(&text).map(|labels| {
+            app.set_focus(VenomFocus::MainView);
+            app.task_view_mut().set_label_filter(labels);
+            app.update_view();
+        }),
+        PromptKind::SaveView => app
+            .save_view(&text)
+            .map(|()| app.set_focus(VenomFocus::MainView)),
+        PromptKind::Sort => text.parse::<Sort>().map(|sort| {
+            app.set_focus(VenomFocus::MainView);
+            app.task_view_mut().set_sort(sort);
+            app.update_view();
+        }),
+        PromptKind::Query => Query::parse(&text).map(|query| {
+            app.set_focus(VenomFocus::MainView);
+            app.task_view_mut().set_query(query);
+            app.update_view();
+        }),
+        PromptKind::Priority => text
+            .trim()
+            .parse::<Priority>()
+            .map_err(|_| eyre!("\"{text}\" is not a priority, use none, low, medium or high"))
+            .map(|priority| {
+                app.set_focus(VenomFocus::MainView);
+                app.set_target_priority(priority);
+            }),
+        PromptKind::Labels => app
+            .set_target_labels(&text)
+            .map(|()| app.set_focus(VenomFocus::MainView)),
+        PromptKind::DueDate => app
+            .set_target_due_dates(&text)
+            .map(|()| app.set_focus(VenomFocus::MainView)),
+    };
+    if let Err(err) = result {
+        popup.borrow_mut().set_error(&err.to_string());
+    }
+}
+
 fn escape_label_popup(app: &mut Venom, popup: &RefCell<EditLabelsPopup>) {
     if popup.borrow().text_editor().mode != EditorMode::Normal {
         popup.borrow_mut().text_editor_mut().mode = EditorMode::Normal;
     } else {
+        let before = app.snapshot();
         let mut line = String::new();
         let mut previous_row = 0;
         let mut tags = vec![];
@@ -206,48 +465,40 @@ fn escape_label_popup(app: &mut Venom, popup: &RefCell<EditLabelsPopup>) {
             app.task_db_mut().remove_label(&s);
         }
 
-        for task in app.task_db().tasks().iter() {
-            let mut task = task.borrow_mut();
-            match task.label() {
-                None => {}
-                Some(label) => {
-                    let tag = label.borrow().short_name().iter().collect::<String>();
-                    task.set_label(app.task_db().label_by_tag(&tag));
-                }
-            }
-        }
+        app.task_db().link_labels();
+        app.record_undo(before);
         app.save_file();
         app.set_focus(VenomFocus::MainView);
     }
 }
 
 fn escape_task_edit(app: &mut Venom, popup: &RefCell<EditTaskPopup>) {
-    popup.borrow_mut().set_focus(EditTaskFocus::Fields);
-    let mut text = String::new();
-    let mut previous_row = 0;
-    popup.borrow().text_editor().lines.iter().for_each(|c| {
-        let (c, idx) = c;
-        if idx.row > previous_row {
-            previous_row = idx.row;
-            text.push('\n');
-        }
-        if let Some(c) = c {
-            text.push(*c);
-        }
-    });
+    let text = popup.borrow().text();
 
-    let popup_binding = popup.borrow();
-    let task = popup_binding.task();
-    match popup.borrow().property() {
-        crate::venom::EditableTaskProperty::Label => {
-            let label = app.task_db().label_by_tag(&text);
-            task.borrow_mut().set_label(label);
-        }
-        _ => {
-            task.borrow_mut()
-                .set_property_from_str(popup.borrow().property(), &text);
-        }
+    let before = app.snapshot();
+    let task = Rc::clone(popup.borrow().task());
+    let property = popup.borrow().property();
+    let result = match property {
+        crate::venom::EditableTaskProperty::Labels => app
+            .task_db()
+            .labels_by_tags(&text)
+            .map(|labels| {
+                task.borrow_mut().set_labels(labels);
+            }),
+        _ => task.borrow_mut().set_property_from_str(property, &text),
+    };
+    // stay in the editor so the text can be fixed
+    if let Err(err) = result {
+        popup.borrow_mut().set_error(Some(err.to_string()));
+        return;
     }
+    // show the property the way it was understood, like the date that "tomorrow" turned into
+    let text = task.borrow().text_to_edit(property);
+    popup
+        .borrow_mut()
+        .load_text(&text)
+        .set_focus(EditTaskFocus::Fields);
+    app.record_undo(before);
     app.save_file();
     app.update_view();
 }
@@ -1,12 +1,28 @@
+use crate::config::Config;
 use crate::edit_labels_popup::EditLabelsPopup;
 use crate::edit_task_popup::EditTaskPopup;
-use crate::task::{self, Task};
-use crate::task_view::TaskView;
+use crate::task::{self, LabelChange, Task, TaskId};
+use crate::due_date::{days_before, parse_due_date, Shift};
+use crate::history::{History, Snapshot};
+use crate::open_database_popup::OpenDatabasePopup;
+use crate::prompt_popup::{PromptKind, PromptPopup};
+use crate::query::Query;
+use crate::{paths, storage};
+use crate::task_view::{CompletedTaskView, TaskView, ViewSpec};
+use crate::view_picker_popup::ViewPickerPopup;
+use crate::finder_popup::FinderPopup;
+use crate::archive_popup::ArchivePopup;
+use chrono::{DateTime, Local, Timelike};
+use color_eyre::eyre::{eyre, WrapErr};
+use color_eyre::Result;
+use ratatui::layout::Rect;
+use ratatui::widgets::TableState;
 use std::cell::RefCell;
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 use strum;
 use task::TaskDB;
 
@@ -17,6 +33,8 @@ pub struct Venom {
     should_quit: bool,
     /// The task and labels database
     task_db: TaskDB,
+    /// Where the task database is read from and saved to
+    save_path: PathBuf,
     /// The current index currently hilighted
     selected_task_idx: usize,
     /// What is currently focused by the app
@@ -24,6 +42,80 @@ pub struct Venom {
     /// This is to be removed in favor of task views
     hide_completed: bool,
     task_view: TaskView,
+    /// Error to show to the user until the next key press
+    error_message: Option<String>,
+    /// Undo and redo steps for the task database
+    history: History,
+    /// User settings
+    config: Config,
+    /// The time due dates are compared to, kept up to date every tick
+    now: DateTime<Local>,
+    /// Where things were drawn on the last frame, for the mouse
+    click_areas: ClickAreas,
+    /// Scroll position of the main table, kept between frames so the rows only move when the
+    /// selection would go out of sight
+    table_state: TableState,
+    /// Tasks marked for the next bulk action
+    marked: HashSet<TaskId>,
+    /// Where visual mode was started. Every task between it and the selected task counts as
+    /// marked.
+    visual_anchor: Option<TaskId>,
+}
+
+/// Where parts of the interface were drawn on the last frame, so that mouse clicks can be matched
+/// to what they landed on
+#[derive(Debug, Default, Clone)]
+pub struct ClickAreas {
+    /// Area the rows of the main table are drawn in, below the column names
+    pub table: Rect,
+    /// What each row of the main table shows, the index of a task or `None` for a group header
+    pub table_rows: Vec<Option<usize>>,
+    /// Columns taken up by the done checkboxes
+    pub done_column: Range<u16>,
+    /// Area the field names of the edit task popup are drawn in, while it is open
+    pub fields: Option<Rect>,
+    /// The task last clicked and when, to tell when a click is the second of a double click
+    last_click: Option<(Instant, usize)>,
+}
+
+impl ClickAreas {
+    /// Longest time between the clicks of a double click
+    const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+    /// Index of the task in the row of the main table at the given position
+    pub fn task_at(&self, column: u16, row: u16) -> Option<usize> {
+        if !contains(self.table, column, row) {
+            return None;
+        }
+        self.table_rows
+            .get(usize::from(row - self.table.y))
+            .copied()
+            .flatten()
+    }
+
+    /// Index of the field of the edit task popup at the given position
+    pub fn field_at(&self, column: u16, row: u16) -> Option<usize> {
+        let fields = self.fields.filter(|&fields| contains(fields, column, row))?;
+        Some(usize::from(row - fields.y))
+    }
+
+    /// Note a click on a task. Returns whether it finishes a double click on that task.
+    pub fn click_task(&mut self, idx: usize) -> bool {
+        let now = Instant::now();
+        let double = self.last_click.is_some_and(|(time, last)| {
+            last == idx && now.duration_since(time) <= Self::DOUBLE_CLICK
+        });
+        // a third click starts over rather than making another double click
+        self.last_click = match double {
+            true => None,
+            false => Some((now, idx)),
+        };
+        double
+    }
+}
+
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    (area.left()..area.right()).contains(&column) && (area.top()..area.bottom()).contains(&row)
 }
 
 /// The Current Focus of a Venom Application
@@ -36,6 +128,16 @@ pub enum VenomFocus {
     EditTaskPopup(Rc<RefCell<EditTaskPopup>>),
     /// A popup to edit the available labels
     EditLabelsPopup(Rc<RefCell<EditLabelsPopup>>),
+    /// A popup to switch to another task database
+    OpenDatabasePopup(Rc<RefCell<OpenDatabasePopup>>),
+    /// A popup asking for a line of text
+    PromptPopup(Rc<RefCell<PromptPopup>>),
+    /// A popup to switch to a named view
+    ViewPickerPopup(Rc<RefCell<ViewPickerPopup>>),
+    /// A popup to search every task by title and notes
+    FinderPopup(Rc<RefCell<FinderPopup>>),
+    /// A popup to browse and restore archived tasks
+    ArchivePopup(Rc<RefCell<ArchivePopup>>),
 }
 
 #[derive(
@@ -54,58 +156,161 @@ pub enum VenomFocus {
 pub enum EditableTaskProperty {
     #[default]
     Title,
-    Label,
+    Labels,
     DueDate,
+    Repeat,
     Notes,
     Priority,
 }
 
 impl Venom {
-    /// Use this as the save path (from the home directory) for save and config files
-    const SAVE_DIR_STR: &'static str = ".venom";
-    /// Use this as the default save file name
-    const DEFAULT_SAVE_FILE_STR: &'static str = "todo.json";
-
-    pub fn new() -> Self {
-        let mut app = Self::default();
-        app.read_from_file();
+    /// Load the app state from the given save file. Fails rather than starting with an empty
+    /// database if the save file exists but can not be read, so that it is never overwritten.
+    pub fn new(save_path: PathBuf, config: Config) -> Result<Self> {
+        let mut app = Self {
+            save_path,
+            config,
+            now: Local::now(),
+            ..Default::default()
+        };
+        app.task_view
+            .set_completed_task_view(app.config.defaults.completed_view);
+        app.read_from_file()?;
+        if let Some(view) = app.config.defaults.view.clone() {
+            if let Err(err) = app.switch_view_named(&view) {
+                app.report_error(&err.wrap_err(format!("could not start in the view {view}")));
+            }
+        }
         app.update_view();
-        app
+        Ok(app)
     }
 
-    pub fn read_from_file(&mut self) {
-        let file_path = self.save_path();
-        let file = File::open(file_path.to_str().unwrap());
-        if let Ok(file) = file {
-            let db = serde_json::from_reader(file);
-            if let Ok(db) = db {
-                self.task_db = db;
+    pub fn read_from_file(&mut self) -> Result<()> {
+        self.task_db = storage::load(&self.save_path)?;
+        if let Err(err) = self.archive_old_tasks() {
+            self.report_error(&err);
+        }
+        Ok(())
+    }
+
+    /// Archive the tasks which have been done for longer than the config keeps them around
+    fn archive_old_tasks(&mut self) -> Result<()> {
+        let Some(days) = self.config.defaults.archive_after_days else {
+            return Ok(());
+        };
+        let before = days_before(self.now, days)
+            .wrap_err("could not work out which tasks to archive from archive_after_days")?;
+        if self.task_db.archive_done(Some(before)) > 0 {
+            self.save_file();
+        }
+        Ok(())
+    }
+
+    /// User settings
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The file the task database is saved to
+    pub fn save_path(&self) -> &Path {
+        &self.save_path
+    }
+
+    /// Switch over to another task database. If it can not be read the current one stays open.
+    pub fn open_database(&mut self, path: PathBuf) {
+        let db = match storage::load(&path) {
+            Ok(db) => db,
+            Err(err) => {
+                self.report_error(&err);
+                return;
             }
+        };
+        self.task_db = db;
+        self.save_path = path;
+        self.history = History::default();
+        if let Err(err) = self.archive_old_tasks() {
+            self.report_error(&err);
+        }
+        self.selected_task_idx = 0;
+        self.update_view();
+        if !self.save_path.exists() {
+            self.save_file();
+        }
+        if let Err(err) = paths::remember_database(&self.save_path) {
+            self.report_error(&err);
         }
     }
 
-    pub fn save_path(&self) -> PathBuf {
-        self.save_dir_path().join(Self::DEFAULT_SAVE_FILE_STR)
+    /// Save the database, showing any error to the user rather than stopping the app
+    pub fn save_file(&mut self) {
+        if let Err(err) = self.try_save_file() {
+            self.report_error(&err);
+        }
+    }
+
+    /// Save the database
+    pub fn try_save_file(&self) -> Result<()> {
+        storage::save(self.task_db(), self.save_path())
+    }
+
+    /// Keep an error around to be shown in the interface
+    pub fn report_error(&mut self, err: &color_eyre::Report) {
+        let message = err
+            .chain()
+            .map(|cause| cause.to_string())
+            .collect::<Vec<_>>()
+            .join(": ");
+        self.error_message = Some(message);
+    }
+
+    /// The last reported error, if it has not been dismissed
+    pub fn error_message(&self) -> Option<&str> {
+        self.error_message.as_deref()
     }
 
-    pub fn save_dir_path(&self) -> PathBuf {
-        let home_path = dirs::home_dir().unwrap();
-        home_path.join(Self::SAVE_DIR_STR)
+    /// Forget the last reported error
+    pub fn clear_error(&mut self) {
+        self.error_message = None;
     }
 
-    pub fn save_file(&self) {
-        let write_path = self.save_dir_path();
-        if !write_path.is_dir() {
-            std::fs::create_dir_all(&write_path).unwrap();
+    /// To do every tick. Once a minute old tasks are archived and the view is regenerated, since a
+    /// task may have become overdue or a query like `due:today` may have moved on a day.
+    pub fn tick(&mut self) {
+        let now = Local::now();
+        let new_minute = now.minute() != self.now.minute();
+        self.now = now;
+        if !new_minute {
+            return;
         }
-        let file_path = self.save_path();
-        let mut file = std::fs::File::create(file_path.to_str().unwrap()).unwrap();
-        let bytes = serde_json::to_vec_pretty(self.task_db()).unwrap();
-        file.write_all(&bytes).unwrap();
+        // any error was already shown when the database was opened
+        let _ = self.archive_old_tasks();
+        let selected = self.task_view.has_tasks().then(|| self.selected_task());
+        self.update_view();
+        if let Some(selected) = selected {
+            self.select_task(&selected);
+        }
+    }
+
+    /// The time due dates are compared to
+    pub fn now(&self) -> DateTime<Local> {
+        self.now
     }
 
-    /// To do every tick
-    pub fn tick(&self) {}
+    pub fn table_state(&self) -> &TableState {
+        &self.table_state
+    }
+
+    pub fn table_state_mut(&mut self) -> &mut TableState {
+        &mut self.table_state
+    }
+
+    pub fn click_areas(&self) -> &ClickAreas {
+        &self.click_areas
+    }
+
+    pub fn click_areas_mut(&mut self) -> &mut ClickAreas {
+        &mut self.click_areas
+    }
 
     /// Flag that the aplication should quit
     pub fn quit(&mut self) {
@@ -116,12 +321,222 @@ impl Venom {
         self.should_quit
     }
 
+    /// Snapshot of the database to pass to [`Venom::record_undo`] once it has been changed
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        Snapshot::of(&self.task_db).ok()
+    }
+
+    /// Add an undo step going back to `before`, unless the database did not actually change
+    pub fn record_undo(&mut self, before: Option<Snapshot>) {
+        let (Some(before), Some(after)) = (before, self.snapshot()) else {
+            return;
+        };
+        if before != after {
+            self.history.record(before);
+        }
+    }
+
+    /// Go back to the state before the last change
+    pub fn undo(&mut self) {
+        let Some(current) = self.snapshot() else {
+            return;
+        };
+        if let Some(previous) = self.history.undo(current) {
+            self.restore(previous);
+        }
+    }
+
+    /// Reapply the last change which was undone
+    pub fn redo(&mut self) {
+        let Some(current) = self.snapshot() else {
+            return;
+        };
+        if let Some(next) = self.history.redo(current) {
+            self.restore(next);
+        }
+    }
+
+    /// Replace the database with a snapshot and save it
+    fn restore(&mut self, snapshot: Snapshot) {
+        match snapshot.restore(&self.task_db) {
+            Ok(db) => {
+                self.task_db = db;
+                self.update_view();
+                self.save_file();
+            }
+            Err(err) => self.report_error(&err),
+        }
+    }
+
+    /// Mark the selected task for bulk actions, or unmark it, and move on to the next task
+    pub fn toggle_mark(&mut self) {
+        let id = self.selected_task().borrow().id();
+        if !self.marked.remove(&id) {
+            self.marked.insert(id);
+        }
+        if self.selected_task_idx + 1 < self.task_view.num_tasks() {
+            self.increment_task_idx();
+        }
+    }
+
+    /// Start marking every task between here and wherever the selection moves to. Leaving visual
+    /// mode keeps those tasks marked.
+    pub fn toggle_visual_mode(&mut self) {
+        if self.visual_anchor.is_some() {
+            self.marked = self.marked_ids();
+            self.visual_anchor = None;
+        } else {
+            self.visual_anchor = Some(self.selected_task().borrow().id());
+        }
+    }
+
+    pub fn is_visual_mode(&self) -> bool {
+        self.visual_anchor.is_some()
+    }
+
+    /// Forget the marked tasks and leave visual mode
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.visual_anchor = None;
+    }
+
+    /// Whether any tasks are marked, including through visual mode
+    pub fn has_marks(&self) -> bool {
+        !self.marked.is_empty() || self.visual_anchor.is_some()
+    }
+
+    /// Ids of the marked tasks along with those in the visual mode range
+    pub fn marked_ids(&self) -> HashSet<TaskId> {
+        let mut ids = self.marked.clone();
+        let tasks = self.task_view.tasks();
+        let anchor = self
+            .visual_anchor
+            .and_then(|anchor| tasks.iter().position(|task| task.borrow().id() == anchor));
+        if let Some(anchor) = anchor {
+            let start = std::cmp::min(anchor, self.selected_task_idx);
+            let end = std::cmp::max(anchor, self.selected_task_idx);
+            for task in tasks.iter().take(end + 1).skip(start) {
+                ids.insert(task.borrow().id());
+            }
+        }
+        ids
+    }
+
+    /// Tasks that actions apply to, the marked ones or otherwise the selected one
+    pub fn target_tasks(&self) -> Vec<Rc<RefCell<Task>>> {
+        if !self.has_marks() {
+            return match self.task_view.has_tasks() {
+                true => vec![self.selected_task()],
+                false => vec![],
+            };
+        }
+        let ids = self.marked_ids();
+        self.task_db
+            .tasks_iter()
+            .filter(|task| ids.contains(&task.borrow().id()))
+            .cloned()
+            .collect()
+    }
+
+    /// Change every target task as one undo step, then forget the marks
+    fn update_target_tasks(&mut self, mut update: impl FnMut(&mut Self, &Rc<RefCell<Task>>)) {
+        let before = self.snapshot();
+        let selected = self.task_view.has_tasks().then(|| self.selected_task());
+        for task in self.target_tasks() {
+            update(self, &task);
+        }
+        self.clear_marks();
+        self.record_undo(before);
+        self.save_file();
+        self.update_view();
+        if let Some(selected) = selected {
+            self.select_task(&selected);
+        }
+    }
+
+    /// Remove all of the marked tasks in one step
+    pub fn remove_marked_tasks(&mut self) {
+        self.update_target_tasks(|app, task| app.task_db.remove_task(task));
+    }
+
+    /// Mark the target tasks done, or if they all are already, mark them not done
+    pub fn toggle_target_tasks_done(&mut self) {
+        let all_done = self.target_tasks().iter().all(|task| task.borrow().is_done());
+        self.update_target_tasks(|app, task| {
+            if task.borrow().is_done() != all_done {
+                return;
+            }
+            task.borrow_mut().toggle_done();
+            if app.config.defaults.complete_subtasks && task.borrow().is_done() {
+                app.task_db.complete_descendants(task.borrow().id());
+            }
+        });
+    }
+
+    /// Move the finished target tasks into the archive
+    pub fn archive_target_tasks(&mut self) -> Result<()> {
+        if !self.target_tasks().iter().any(|task| task.borrow().is_done()) {
+            return Err(eyre!("only finished tasks can be archived"));
+        }
+        self.update_target_tasks(|app, task| {
+            let (id, done) = (task.borrow().id(), task.borrow().is_done());
+            if done {
+                let _ = app.task_db.archive_task(id);
+            }
+        });
+        Ok(())
+    }
+
+    pub fn set_target_priority(&mut self, priority: task::Priority) {
+        self.update_target_tasks(|_, task| {
+            task.borrow_mut().set_priority(priority);
+        });
+    }
+
+    /// Change the labels of the target tasks as described by [`LabelChange::parse`]
+    pub fn set_target_labels(&mut self, text: &str) -> Result<()> {
+        let change = LabelChange::parse(text, &self.task_db)?;
+        self.update_target_tasks(|_, task| {
+            let labels = change.apply(task.borrow().labels());
+            task.borrow_mut().set_labels(labels);
+        });
+        Ok(())
+    }
+
+    /// Move the due dates of the target tasks by an amount like `+2d` or `-1w`, skipping tasks
+    /// without a due date, or set them all to a date like `fri`
+    pub fn set_target_due_dates(&mut self, text: &str) -> Result<()> {
+        if let Some(shift) = Shift::parse(text) {
+            let mut shifted = vec![];
+            for task in self.target_tasks() {
+                if let Some(due) = task.borrow().due_date() {
+                    shifted.push((Rc::clone(&task), shift.apply(due)?));
+                }
+            }
+            self.update_target_tasks(|_, task| {
+                if let Some((_, due)) = shifted.iter().find(|(other, _)| Rc::ptr_eq(other, task)) {
+                    task.borrow_mut().set_date(due);
+                }
+            });
+        } else {
+            let due = parse_due_date(text, Local::now())?;
+            self.update_target_tasks(|_, task| {
+                match due {
+                    Some(due) => task.borrow_mut().set_date(&due),
+                    None => task.borrow_mut().set_no_date(),
+                };
+            });
+        }
+        Ok(())
+    }
+
     /// Remove the selected task in the current view from the database
     pub fn remove_selected_task(&mut self) {
+        let before = self.snapshot();
         let task = self.selected_task();
         self.task_db.remove_task(&task);
-        self.selected_task_idx =
-            std::cmp::min(self.selected_task_idx, self.task_db.num_tasks() - 1);
+        self.record_undo(before);
+        self.update_view();
     }
 
     /// task view
@@ -154,9 +569,122 @@ impl Venom {
         self.task_view.tasks()[self.selected_task_idx].clone()
     }
 
-    /// mark the current highlighted task as done / not done.
+    /// mark the current highlighted task as done / not done. With
+    /// [`crate::config::Defaults::complete_subtasks`] set, finishing a task finishes its subtasks
+    /// too.
     pub fn toggle_selected_task(&mut self) {
-        self.selected_task().borrow_mut().toggle_done();
+        let before = self.snapshot();
+        let task = self.selected_task();
+        task.borrow_mut().toggle_done();
+        if self.config.defaults.complete_subtasks && task.borrow().is_done() {
+            self.task_db.complete_descendants(task.borrow().id());
+        }
+        self.record_undo(before);
+    }
+
+    /// Highlight the given task if it is in the current view
+    pub fn select_task(&mut self, task: &Rc<RefCell<Task>>) {
+        if let Some(idx) = self.task_view.tasks().iter().position(|t| Rc::ptr_eq(t, task)) {
+            self.selected_task_idx = idx;
+        }
+    }
+
+    /// Swap the selected task with the next task at the same level in the manual order, moving it
+    /// down the list, or up it with `up`
+    pub fn move_selected_task(&mut self, up: bool) {
+        let Some(descending) = self.task_view.sort().manual() else {
+            self.error_message = Some("switch to the manual sort order to move tasks".to_string());
+            return;
+        };
+        let idx = self.selected_task_idx;
+        let depth = self.task_view.depth(idx);
+        let at_same_level = |other: &usize| self.task_view.depth(*other) == depth;
+        let above_or_below = |other: &usize| self.task_view.depth(*other) >= depth;
+        let neighbour = match up {
+            true => (0..idx)
+                .rev()
+                .take_while(above_or_below)
+                .find(at_same_level),
+            false => (idx + 1..self.task_view.num_tasks())
+                .take_while(above_or_below)
+                .find(at_same_level),
+        };
+        let Some(neighbour) = neighbour else {
+            return;
+        };
+
+        let before = self.snapshot();
+        let task = self.selected_task();
+        let id = task.borrow().id();
+        let next_to = self.task_view.tasks()[neighbour].borrow().id();
+        // going down the list is going later in the database, unless the order is reversed
+        self.task_db.move_task(id, next_to, up == descending);
+        self.record_undo(before);
+        self.update_view();
+        self.select_task(&task);
+        self.save_file();
+    }
+
+    /// Make the selected task a subtask of the closest task above it at the same level
+    pub fn indent_selected_task(&mut self) {
+        let idx = self.selected_task_idx;
+        let depth = self.task_view.depth(idx);
+        let sibling = (0..idx)
+            .rev()
+            .take_while(|&above| self.task_view.depth(above) >= depth)
+            .find(|&above| self.task_view.depth(above) == depth);
+        let Some(sibling) = sibling else {
+            self.error_message = Some("there is no task above to indent under".to_string());
+            return;
+        };
+        let parent = self.task_view.tasks()[sibling].borrow().id();
+        self.reparent_selected_task(Some(parent));
+    }
+
+    /// Move the selected subtask up a level, next to its current parent
+    pub fn outdent_selected_task(&mut self) {
+        let Some(parent) = self.selected_task().borrow().parent() else {
+            return;
+        };
+        let grandparent = self
+            .task_db
+            .task_by_id(parent)
+            .and_then(|task| task.borrow().parent());
+        self.reparent_selected_task(grandparent);
+    }
+
+    fn reparent_selected_task(&mut self, parent: Option<task::TaskId>) {
+        let before = self.snapshot();
+        let task = self.selected_task();
+        let id = task.borrow().id();
+        if let Err(err) = self.task_db.set_parent(id, parent) {
+            self.report_error(&err);
+            return;
+        }
+        if let Some(parent) = parent.and_then(|parent| self.task_db.task_by_id(parent)) {
+            // keep the moved task in sight
+            if parent.borrow().is_collapsed() {
+                parent.borrow_mut().toggle_collapsed();
+            }
+        }
+        self.record_undo(before);
+        self.update_view();
+        self.select_task(&task);
+        self.save_file();
+    }
+
+    /// Show or hide the subtasks of the selected task
+    pub fn toggle_selected_collapsed(&mut self) {
+        let task = self.selected_task();
+        if self.task_db.children(task.borrow().id()).is_empty() {
+            return;
+        }
+        let before = self.snapshot();
+        task.borrow_mut().toggle_collapsed();
+        self.record_undo(before);
+        self.update_view();
+        self.select_task(&task);
+        self.save_file();
     }
 
     /// The current focus of the main application
@@ -173,7 +701,7 @@ impl Venom {
 
     /// Effectively move down the list, looping back at the top if nesacary
     pub fn increment_task_idx(&mut self) {
-        if self.task_db.has_no_tasks() {
+        if !self.task_view.has_tasks() {
             self.selected_task_idx = 0;
             return;
         }
@@ -186,9 +714,32 @@ impl Venom {
         }
     }
 
+    /// Number of tasks moved over by a page up or down, which is about how many rows fit in the
+    /// main table
+    fn page_size(&self) -> usize {
+        usize::from(self.click_areas.table.height).max(1)
+    }
+
+    pub fn page_down(&mut self) {
+        let last = self.task_view.num_tasks().saturating_sub(1);
+        self.selected_task_idx = std::cmp::min(self.selected_task_idx + self.page_size(), last);
+    }
+
+    pub fn page_up(&mut self) {
+        self.selected_task_idx = self.selected_task_idx.saturating_sub(self.page_size());
+    }
+
+    pub fn select_first_task(&mut self) {
+        self.selected_task_idx = 0;
+    }
+
+    pub fn select_last_task(&mut self) {
+        self.selected_task_idx = self.task_view.num_tasks().saturating_sub(1);
+    }
+
     /// Effectively move up the list, looping back at the bottom if nesacary
     pub fn decrement_task_idx(&mut self) {
-        if self.task_db.has_no_tasks() {
+        if !self.task_view.has_tasks() {
             self.selected_task_idx = 0;
             return;
         }
@@ -201,7 +752,7 @@ impl Venom {
         }
     }
     pub fn update_view(&mut self) {
-        self.task_view.generate_displayed_list(&self.task_db);
+        self.task_view.generate_displayed_list(&self.task_db, self.now);
 
         self.selected_task_idx = {
             let num_in_view = self.task_view().tasks().len();
@@ -223,15 +774,236 @@ impl Venom {
         self.update_view();
     }
 
+    /// Ask for the labels to filter on, starting from the current filter
+    pub fn filter_labels(&mut self) {
+        let current = self
+            .task_view
+            .label_filter()
+            .iter()
+            .map(|label| label.borrow().short_name_string().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let popup = PromptPopup::new(PromptKind::LabelFilter).with_text(&current);
+        self.focus = VenomFocus::PromptPopup(Rc::new(RefCell::new(popup)));
+    }
+
+    /// Every named view, those from the config first and then those saved in the database. Saved
+    /// views with the same name as one in the config are left out.
+    pub fn views(&self) -> Vec<ViewSpec> {
+        let mut views = self.config.views.clone();
+        for view in self.task_db.views() {
+            if !views.iter().any(|other| other.name == view.name) {
+                views.push(view.clone());
+            }
+        }
+        views
+    }
+
+    /// Whether a view comes from the config rather than being saved from the app
+    pub fn is_config_view(&self, name: &str) -> bool {
+        self.config.views.iter().any(|view| view.name == name)
+    }
+
+    /// Switch to the view at `idx` of [`Venom::views`]
+    pub fn switch_view(&mut self, idx: usize) {
+        let Some(view) = self.views().get(idx).cloned() else {
+            self.error_message = Some(format!("there is no view number {}", idx + 1));
+            return;
+        };
+        if let Err(err) = self.task_view.apply(&view, &self.task_db) {
+            self.report_error(&err);
+        }
+        self.update_view();
+    }
+
+    /// Switch to the view with the given name
+    pub fn switch_view_named(&mut self, name: &str) -> Result<()> {
+        let view = self
+            .views()
+            .into_iter()
+            .find(|view| view.name == name)
+            .ok_or_else(|| eyre!("there is no view called {name}"))?;
+        self.task_view.apply(&view, &self.task_db)?;
+        self.update_view();
+        Ok(())
+    }
+
+    /// Go back to the unnamed view showing every task
+    pub fn default_view(&mut self) {
+        self.task_view.reset(self.config.defaults.completed_view);
+        self.update_view();
+    }
+
+    /// Save the current view settings under a name in the task database and switch to it
+    pub fn save_view(&mut self, name: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(eyre!("the view needs a name"));
+        }
+        if self.is_config_view(name) {
+            return Err(eyre!("{name} is defined in the config file"));
+        }
+        let before = self.snapshot();
+        let view = self.task_view.spec(name);
+        self.task_db.save_view(view);
+        self.task_view.set_name(Some(name.to_string()));
+        self.record_undo(before);
+        self.save_file();
+        Ok(())
+    }
+
+    /// Remove a view saved in the task database
+    pub fn delete_view(&mut self, name: &str) -> Result<()> {
+        if self.is_config_view(name) {
+            return Err(eyre!("{name} is defined in the config file"));
+        }
+        let before = self.snapshot();
+        if !self.task_db.remove_view(name) {
+            return Err(eyre!("there is no saved view called {name}"));
+        }
+        if self.task_view.name() == Some(name) {
+            self.task_view.set_name(None);
+        }
+        self.record_undo(before);
+        self.save_file();
+        Ok(())
+    }
+
+    /// Open a popup listing the named views
+    pub fn pick_view(&mut self) {
+        let current = self.task_view.name();
+        let popup = ViewPickerPopup::new(self.views())
+            .with_selected_where(|view| Some(view.name.as_str()) == current);
+        self.focus = VenomFocus::ViewPickerPopup(Rc::new(RefCell::new(popup)));
+    }
+
+    /// Open a popup to fuzzy search every task in the database
+    pub fn find(&mut self) {
+        let popup = FinderPopup::new(self.task_db.tasks().clone());
+        self.focus = VenomFocus::FinderPopup(Rc::new(RefCell::new(popup)));
+    }
+
+    /// Open a popup listing the archived tasks, most recently finished first, with the one at
+    /// `selected_idx` selected
+    pub fn browse_archive(&mut self, selected_idx: usize) {
+        let mut tasks = self.task_db.archive().to_vec();
+        tasks.sort_by_key(|task| std::cmp::Reverse(task.borrow().completed()));
+        let popup = ArchivePopup::new(tasks).with_selected(selected_idx);
+        self.focus = VenomFocus::ArchivePopup(Rc::new(RefCell::new(popup)));
+    }
+
+    /// Move an archived task back into the task list
+    pub fn restore_archived_task(&mut self, task: &Rc<RefCell<Task>>) {
+        let before = self.snapshot();
+        let id = task.borrow().id();
+        if self.task_db.restore_task(id).is_none() {
+            return;
+        }
+        self.record_undo(before);
+        self.save_file();
+        self.update_view();
+        self.select_task(task);
+    }
+
+    /// Delete an archived task for good
+    pub fn remove_archived_task(&mut self, task: &Rc<RefCell<Task>>) {
+        let before = self.snapshot();
+        let id = task.borrow().id();
+        if self.task_db.remove_archived_task(id).is_none() {
+            return;
+        }
+        self.record_undo(before);
+        self.save_file();
+    }
+
+    /// Select a task found with the finder. Folded parents are unfolded, and if the view still
+    /// does not show the task it goes back to showing every task.
+    pub fn jump_to_task(&mut self, task: &Rc<RefCell<Task>>) {
+        self.focus = VenomFocus::MainView;
+        let mut unfolded = false;
+        let mut parent = task.borrow().parent();
+        while let Some(ancestor) = parent.and_then(|id| self.task_db.task_by_id(id)) {
+            if ancestor.borrow().is_collapsed() {
+                ancestor.borrow_mut().toggle_collapsed();
+                unfolded = true;
+            }
+            parent = ancestor.borrow().parent();
+        }
+        if unfolded {
+            self.save_file();
+        }
+        self.update_view();
+
+        if !self.task_view.tasks().iter().any(|shown| Rc::ptr_eq(shown, task)) {
+            let completed = match (task.borrow().is_done(), self.config.defaults.completed_view) {
+                (true, CompletedTaskView::Hide) => CompletedTaskView::Seperate,
+                (_, completed) => completed,
+            };
+            self.task_view.reset(completed);
+            self.update_view();
+        }
+        self.select_task(task);
+    }
+
+    /// Ask for a name to save the current view settings under
+    pub fn prompt_save_view(&mut self) {
+        let current = self.task_view.name().unwrap_or_default();
+        let popup = PromptPopup::new(PromptKind::SaveView).with_text(current);
+        self.focus = VenomFocus::PromptPopup(Rc::new(RefCell::new(popup)));
+    }
+
+    /// Ask for the order to show tasks in, starting from the current one
+    pub fn prompt_sort(&mut self) {
+        let current = self.task_view.sort().to_string();
+        let popup = PromptPopup::new(PromptKind::Sort).with_text(&current);
+        self.focus = VenomFocus::PromptPopup(Rc::new(RefCell::new(popup)));
+    }
+
+    /// Ask for a query to filter the main view with, starting from the current one
+    pub fn query(&mut self) {
+        let current = self.task_view.query().map(Query::text).unwrap_or_default();
+        let popup = PromptPopup::new(PromptKind::Query).with_text(current);
+        self.focus = VenomFocus::PromptPopup(Rc::new(RefCell::new(popup)));
+    }
+
+    pub fn toggle_label_match(&mut self) {
+        self.task_view_mut().toggle_label_match();
+        self.update_view();
+    }
+
+    pub fn toggle_group_by(&mut self) {
+        self.task_view_mut().toggle_group_by();
+        self.update_view();
+    }
+
     /// Add a blank task and then open up the editing popup for it
     pub fn add_task(&mut self) {
+        let before = self.snapshot();
         let task = Rc::new(RefCell::new(Task::default()));
         self.task_db.add_task(Rc::clone(&task));
+        self.record_undo(before);
         self.selected_task_idx = 0;
         self.update_view();
         self.edit_task(task);
     }
 
+    /// Add a blank subtask to the selected task and then open up the editing popup for it
+    pub fn add_subtask(&mut self) {
+        let before = self.snapshot();
+        let parent = self.selected_task();
+        if parent.borrow().is_collapsed() {
+            parent.borrow_mut().toggle_collapsed();
+        }
+        let task = Task::builder()
+            .with_parent(Some(parent.borrow().id()))
+            .build_rcc();
+        self.task_db.add_task(Rc::clone(&task));
+        self.record_undo(before);
+        self.update_view();
+        self.select_task(&task);
+        self.edit_task(task);
+    }
+
     /// Copy a task and then edit the new one
     pub fn add_task_based_on_current(&mut self) {
         let current_task = self.selected_task();
@@ -240,9 +1012,13 @@ impl Venom {
             .with_title(current_task_borrow.title())
             .with_notes(current_task_borrow.notes())
             .with_due_date(current_task_borrow.due_date())
-            .with_label(current_task_borrow.label().clone())
+            .with_labels(current_task_borrow.labels().to_vec())
+            .with_recurrence(current_task_borrow.recurrence())
+            .with_parent(current_task_borrow.parent())
             .build_rcc();
+        let before = self.snapshot();
         self.task_db.add_task(Rc::clone(&task));
+        self.record_undo(before);
         self.selected_task_idx = 0;
         self.edit_task(task);
         self.update_view();
@@ -268,6 +1044,23 @@ impl Venom {
         self.focus = VenomFocus::EditTaskPopup(popup);
     }
 
+    /// Open a popup listing the task databases in the data directory
+    pub fn pick_database(&mut self) {
+        let mut databases = paths::databases();
+        if !databases.iter().any(|path| path == self.save_path()) {
+            databases.insert(0, self.save_path.clone());
+        }
+        let popup =
+            OpenDatabasePopup::new(databases).with_selected_where(|path| path == self.save_path());
+        self.focus = VenomFocus::OpenDatabasePopup(Rc::new(RefCell::new(popup)));
+    }
+
+    /// Open a popup asking for a line of text
+    pub fn prompt(&mut self, kind: PromptKind) {
+        let popup = PromptPopup::new(kind);
+        self.focus = VenomFocus::PromptPopup(Rc::new(RefCell::new(popup)));
+    }
+
     /// Open a label edit popup
     pub fn edit_labels(&mut self) {
         let popup = Rc::new(RefCell::new(EditLabelsPopup::default()));
@@ -0,0 +1,5 @@
+use crate::list_popup::ListPopup;
+use crate::task_view::ViewSpec;
+
+/// Popup to switch to one of the named views
+pub type ViewPickerPopup = ListPopup<ViewSpec>;